@@ -0,0 +1,256 @@
+use crate::detect::{Detection, DetectorSource};
+use crate::error::AppError;
+use crate::tool::{LANDMARK_COUNT, LANDMARK_REGIONS};
+use dlib_face_recognition::{Point, Rectangle};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::os::raw::c_long;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedFace {
+    pub left: c_long,
+    pub top: c_long,
+    pub right: c_long,
+    pub bottom: c_long,
+    pub detector: DetectorSource,
+    // [x, y] pairs in dlib order; empty when no landmarks are known
+    pub landmarks: Vec<[c_long; 2]>,
+}
+
+impl DetectedFace {
+    pub fn from_detection(detection: &Detection) -> Self {
+        DetectedFace {
+            left: detection.rect.left,
+            top: detection.rect.top,
+            right: detection.rect.right,
+            bottom: detection.rect.bottom,
+            detector: detection.source,
+            landmarks: detection.landmarks.iter().map(|p| [p.x(), p.y()]).collect(),
+        }
+    }
+    pub fn to_detection(&self) -> Detection {
+        Detection {
+            rect: Rectangle {
+                left: self.left,
+                top: self.top,
+                right: self.right,
+                bottom: self.bottom,
+            },
+            source: self.detector,
+            landmarks: self
+                .landmarks
+                .iter()
+                .map(|[x, y]| Point::new(*x, *y))
+                .collect(),
+        }
+    }
+}
+
+/// Every face found in one image, in a form annotation tools can consume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageDetections {
+    pub image_path: String,
+    pub width: u32,
+    pub height: u32,
+    pub faces: Vec<DetectedFace>,
+}
+
+impl ImageDetections {
+    pub fn new(image_path: &str, width: u32, height: u32, detections: &[Detection]) -> Self {
+        ImageDetections {
+            image_path: image_path.to_string(),
+            width,
+            height,
+            faces: detections
+                .iter()
+                .map(DetectedFace::from_detection)
+                .collect(),
+        }
+    }
+    pub fn detections(&self) -> Vec<Detection> {
+        self.faces.iter().map(DetectedFace::to_detection).collect()
+    }
+}
+
+pub fn write_json(images: &[ImageDetections], json_path: &str) -> Result<(), AppError> {
+    fs::write(json_path, serde_json::to_string_pretty(images)?)?;
+    Ok(())
+}
+
+pub fn read_json(json_path: &str) -> Result<Vec<ImageDetections>, AppError> {
+    Ok(serde_json::from_str(&fs::read_to_string(json_path)?)?)
+}
+
+// The subset of the COCO keypoints format we read and write
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CocoDataset {
+    pub images: Vec<CocoImage>,
+    pub annotations: Vec<CocoAnnotation>,
+    pub categories: Vec<CocoCategory>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CocoImage {
+    pub id: u64,
+    pub file_name: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CocoAnnotation {
+    pub id: u64,
+    pub image_id: u64,
+    pub category_id: u64,
+    // [x, y, width, height]
+    pub bbox: [f64; 4],
+    pub area: f64,
+    #[serde(default)]
+    pub iscrowd: u8,
+    // [x, y, visibility] triples, visibility 0 meaning unlabelled
+    #[serde(default)]
+    pub keypoints: Vec<f64>,
+    #[serde(default)]
+    pub num_keypoints: usize,
+    // Not part of COCO; missing on boxes added in annotation software
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detector: Option<DetectorSource>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CocoCategory {
+    pub id: u64,
+    pub name: String,
+    pub supercategory: String,
+    pub keypoints: Vec<String>,
+    // 1-based keypoint index pairs
+    pub skeleton: Vec<[usize; 2]>,
+}
+
+const FACE_CATEGORY_ID: u64 = 1;
+
+pub fn to_coco(images: &[ImageDetections]) -> CocoDataset {
+    let mut coco_images = Vec::new();
+    let mut annotations = Vec::new();
+
+    for (i, image) in images.iter().enumerate() {
+        let image_id = i as u64 + 1;
+        coco_images.push(CocoImage {
+            id: image_id,
+            file_name: image.image_path.clone(),
+            width: image.width,
+            height: image.height,
+        });
+
+        for face in &image.faces {
+            let width = (face.right - face.left) as f64;
+            let height = (face.bottom - face.top) as f64;
+            let keypoints = if face.landmarks.len() == LANDMARK_COUNT {
+                face.landmarks
+                    .iter()
+                    .flat_map(|[x, y]| [*x as f64, *y as f64, 2.0])
+                    .collect()
+            } else {
+                vec![0.0; LANDMARK_COUNT * 3]
+            };
+            annotations.push(CocoAnnotation {
+                id: annotations.len() as u64 + 1,
+                image_id,
+                category_id: FACE_CATEGORY_ID,
+                bbox: [face.left as f64, face.top as f64, width, height],
+                area: width * height,
+                iscrowd: 0,
+                num_keypoints: keypoints.chunks(3).filter(|k| k[2] > 0.0).count(),
+                keypoints,
+                detector: Some(face.detector),
+            });
+        }
+    }
+
+    CocoDataset {
+        images: coco_images,
+        annotations,
+        categories: vec![face_category()],
+    }
+}
+
+/// Converts a COCO dataset back, e.g. after boxes were reviewed in annotation
+/// software. Boxes without a detector are imported as `Manual`, and landmarks
+/// are kept only when all 68 keypoints are labelled.
+pub fn from_coco(dataset: &CocoDataset) -> Vec<ImageDetections> {
+    let mut faces_by_image: HashMap<u64, Vec<DetectedFace>> = HashMap::new();
+
+    for annotation in &dataset.annotations {
+        if annotation.category_id != FACE_CATEGORY_ID {
+            continue;
+        }
+        let [x, y, width, height] = annotation.bbox;
+        let labelled = annotation.keypoints.len() == LANDMARK_COUNT * 3
+            && annotation.keypoints.chunks(3).all(|k| k[2] > 0.0);
+        let landmarks = if labelled {
+            annotation
+                .keypoints
+                .chunks(3)
+                .map(|k| [k[0].round() as c_long, k[1].round() as c_long])
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        faces_by_image
+            .entry(annotation.image_id)
+            .or_default()
+            .push(DetectedFace {
+                left: x.round() as c_long,
+                top: y.round() as c_long,
+                right: (x + width).round() as c_long,
+                bottom: (y + height).round() as c_long,
+                detector: annotation.detector.unwrap_or(DetectorSource::Manual),
+                landmarks,
+            });
+    }
+
+    dataset
+        .images
+        .iter()
+        .map(|image| ImageDetections {
+            image_path: image.file_name.clone(),
+            width: image.width,
+            height: image.height,
+            faces: faces_by_image.remove(&image.id).unwrap_or_default(),
+        })
+        .collect()
+}
+
+pub fn write_coco(images: &[ImageDetections], json_path: &str) -> Result<(), AppError> {
+    fs::write(json_path, serde_json::to_string_pretty(&to_coco(images))?)?;
+    Ok(())
+}
+
+pub fn read_coco(json_path: &str) -> Result<Vec<ImageDetections>, AppError> {
+    let dataset: CocoDataset = serde_json::from_str(&fs::read_to_string(json_path)?)?;
+    Ok(from_coco(&dataset))
+}
+
+fn face_category() -> CocoCategory {
+    let mut skeleton = Vec::new();
+    for (_, first, last, closed) in LANDMARK_REGIONS {
+        for i in first..last {
+            skeleton.push([i + 1, i + 2]);
+        }
+        if closed {
+            skeleton.push([last + 1, first + 1]);
+        }
+    }
+
+    CocoCategory {
+        id: FACE_CATEGORY_ID,
+        name: String::from("face"),
+        supercategory: String::from("face"),
+        keypoints: (0..LANDMARK_COUNT)
+            .map(|i| format!("landmark_{}", i))
+            .collect(),
+        skeleton,
+    }
+}
@@ -0,0 +1,148 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// Entry sizes come from the archive header, so preallocation is capped and
+// larger entries grow the buffer as they are actually read
+const MAX_PREALLOCATION: u64 = 16 * 1024 * 1024;
+
+/// One file read out of an archive.
+#[derive(Debug)]
+pub struct ArchiveEntry {
+    // Path inside the archive
+    pub name: String,
+    pub bytes: Vec<u8>,
+    // From the entry header, in local time
+    pub modified: Option<NaiveDateTime>,
+}
+
+// .zip, .tar, .tar.gz and .tgz files are read as photo sources
+pub fn is_archive(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    Path::new(path).is_file()
+        && [".zip", ".tar", ".tar.gz", ".tgz"]
+            .iter()
+            .any(|ext| lower.ends_with(ext))
+}
+
+/// Paths of every entry in the archive, directories excluded.
+pub fn list_archive_entries(archive_path: &str) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    visit_entries(
+        archive_path,
+        |_| false,
+        |name, _, _| names.push(name.to_string()),
+    )?;
+    Ok(names)
+}
+
+/// Calls `f` with each file entry whose name `wanted` returns true for. Entries
+/// are decompressed one at a time, so the archive is never unpacked to disk or
+/// held in memory as a whole.
+pub fn for_each_entry(
+    archive_path: &str,
+    wanted: impl Fn(&str) -> bool,
+    mut f: impl FnMut(ArchiveEntry),
+) -> Result<(), String> {
+    visit_entries(archive_path, wanted, |name, bytes, modified| {
+        if let Some(bytes) = bytes {
+            f(ArchiveEntry {
+                name: name.to_string(),
+                bytes,
+                modified,
+            });
+        }
+    })
+}
+
+// Every file entry is passed to `visit`, with its contents only when `read` says so
+fn visit_entries(
+    archive_path: &str,
+    read: impl Fn(&str) -> bool,
+    mut visit: impl FnMut(&str, Option<Vec<u8>>, Option<NaiveDateTime>),
+) -> Result<(), String> {
+    let file = File::open(archive_path).map_err(|e| e.to_string())?;
+    let lower = archive_path.to_lowercase();
+
+    if lower.ends_with(".zip") {
+        let mut zip = zip::ZipArchive::new(BufReader::new(file)).map_err(|e| e.to_string())?;
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i).map_err(|e| e.to_string())?;
+            if !entry.is_file() {
+                continue;
+            }
+            let name = entry.name().to_string();
+            // Zip timestamps carry no time zone and are conventionally local time
+            let modified = entry.last_modified();
+            let modified = NaiveDate::from_ymd_opt(
+                modified.year() as i32,
+                modified.month() as u32,
+                modified.day() as u32,
+            )
+            .and_then(|date| {
+                date.and_hms_opt(
+                    modified.hour() as u32,
+                    modified.minute() as u32,
+                    modified.second() as u32,
+                )
+            });
+            let bytes = if read(&name) {
+                let size = entry.size();
+                Some(read_entry(&mut entry, size)?)
+            } else {
+                None
+            };
+            visit(&name, bytes, modified);
+        }
+        Ok(())
+    } else if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+        visit_tar(
+            tar::Archive::new(GzDecoder::new(BufReader::new(file))),
+            read,
+            visit,
+        )
+    } else {
+        visit_tar(tar::Archive::new(BufReader::new(file)), read, visit)
+    }
+}
+
+fn visit_tar<R: Read>(
+    mut archive: tar::Archive<R>,
+    read: impl Fn(&str) -> bool,
+    mut visit: impl FnMut(&str, Option<Vec<u8>>, Option<NaiveDateTime>),
+) -> Result<(), String> {
+    for entry in archive.entries().map_err(|e| e.to_string())? {
+        let mut entry = entry.map_err(|e| e.to_string())?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .map_err(|e| e.to_string())?
+            .to_string_lossy()
+            .into_owned();
+        // Tar stores seconds since the epoch; shown in local time like file mtimes
+        let modified = entry
+            .header()
+            .mtime()
+            .ok()
+            .and_then(|secs| DateTime::from_timestamp(secs as i64, 0))
+            .map(|mtime| mtime.with_timezone(&Local).naive_local());
+        let bytes = if read(&name) {
+            let size = entry.size();
+            Some(read_entry(&mut entry, size)?)
+        } else {
+            None
+        };
+        visit(&name, bytes, modified);
+    }
+    Ok(())
+}
+
+fn read_entry(entry: &mut impl Read, size: u64) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(size.min(MAX_PREALLOCATION) as usize);
+    entry.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
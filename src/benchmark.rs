@@ -0,0 +1,218 @@
+use crate::error::AppError;
+use crate::photos::list_image_files;
+use crate::tool::csv_field;
+use dlib_face_recognition::*;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write;
+use std::time::Instant;
+
+// Boxes overlapping at least this much count as the same face
+pub const MATCH_IOU: f64 = 0.5;
+
+#[derive(Debug, Clone)]
+pub struct ImageBenchmark {
+    pub image_path: String,
+    pub hog_ms: f64,
+    pub cnn_ms: f64,
+    pub hog_faces: usize,
+    pub cnn_faces: usize,
+    // Pairs of HOG and CNN boxes with IoU >= MATCH_IOU, with their IoU
+    pub matched_ious: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LatencyStats {
+    pub mean: f64,
+    pub median: f64,
+    pub p95: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl LatencyStats {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return LatencyStats::default();
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+
+        LatencyStats {
+            mean: sorted.iter().sum::<f64>() / sorted.len() as f64,
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+/// Runs HOG and CNN over every image in `dir_path`, timing each detector.
+/// Both detectors first run once on the first image, untimed, so one-off setup
+/// costs don't land in the results, and their order alternates per image so
+/// neither always runs on a warm cache. Images that can't be opened are skipped.
+pub fn benchmark_detectors(dir_path: &str) -> Result<Vec<ImageBenchmark>, AppError> {
+    let hog = FaceDetector::default();
+    let cnn = FaceDetectorCnn::default().map_err(AppError::Model)?;
+
+    Ok(list_image_files(dir_path)
+        .into_iter()
+        .filter_map(|image_path| {
+            let image = image::open(&image_path).ok()?.to_rgb8();
+            Some((image_path, ImageMatrix::from_image(&image)))
+        })
+        .enumerate()
+        .map(|(i, (image_path, matrix))| {
+            if i == 0 {
+                hog.face_locations(&matrix);
+                cnn.face_locations(&matrix);
+            }
+
+            let ((hog_boxes, hog_ms), (cnn_boxes, cnn_ms)) = if i % 2 == 0 {
+                let hog_run = timed(|| hog.face_locations(&matrix));
+                (hog_run, timed(|| cnn.face_locations(&matrix)))
+            } else {
+                let cnn_run = timed(|| cnn.face_locations(&matrix));
+                (timed(|| hog.face_locations(&matrix)), cnn_run)
+            };
+
+            ImageBenchmark {
+                hog_faces: hog_boxes.len(),
+                cnn_faces: cnn_boxes.len(),
+                matched_ious: match_boxes(&hog_boxes, &cnn_boxes),
+                image_path,
+                hog_ms,
+                cnn_ms,
+            }
+        })
+        .collect())
+}
+
+pub fn iou(a: &Rectangle, b: &Rectangle) -> f64 {
+    let width = (a.right.min(b.right) - a.left.max(b.left)).max(0) as f64;
+    let height = (a.bottom.min(b.bottom) - a.top.max(b.top)).max(0) as f64;
+    let intersection = width * height;
+    let area = |r: &Rectangle| (r.width().max(0) * r.height().max(0)) as f64;
+    let union = area(a) + area(b) - intersection;
+
+    if union > 0.0 {
+        intersection / union
+    } else {
+        0.0
+    }
+}
+
+// Greedy one-to-one matching, best overlaps first
+fn match_boxes(a: &[Rectangle], b: &[Rectangle]) -> Vec<f64> {
+    let mut candidates: Vec<(usize, usize, f64)> = Vec::new();
+    for (i, ra) in a.iter().enumerate() {
+        for (j, rb) in b.iter().enumerate() {
+            let overlap = iou(ra, rb);
+            if overlap >= MATCH_IOU {
+                candidates.push((i, j, overlap));
+            }
+        }
+    }
+    candidates.sort_by(|x, y| y.2.total_cmp(&x.2));
+
+    let mut used_a = vec![false; a.len()];
+    let mut used_b = vec![false; b.len()];
+    let mut matched = Vec::new();
+    for (i, j, overlap) in candidates {
+        if !used_a[i] && !used_b[j] {
+            used_a[i] = true;
+            used_b[j] = true;
+            matched.push(overlap);
+        }
+    }
+    matched
+}
+
+fn timed<R>(f: impl FnOnce() -> R) -> (R, f64) {
+    let now = Instant::now();
+    let result = f();
+    (result, now.elapsed().as_secs_f64() * 1000.0)
+}
+
+pub fn format_benchmark_summary(results: &[ImageBenchmark]) -> String {
+    let hog_latency =
+        LatencyStats::from_samples(&results.iter().map(|r| r.hog_ms).collect::<Vec<_>>());
+    let cnn_latency =
+        LatencyStats::from_samples(&results.iter().map(|r| r.cnn_ms).collect::<Vec<_>>());
+    let hog_faces: usize = results.iter().map(|r| r.hog_faces).sum();
+    let cnn_faces: usize = results.iter().map(|r| r.cnn_faces).sum();
+    let ious: Vec<f64> = results
+        .iter()
+        .flat_map(|r| r.matched_ious.clone())
+        .collect();
+    let mean_iou = if ious.is_empty() {
+        0.0
+    } else {
+        ious.iter().sum::<f64>() / ious.len() as f64
+    };
+    let hog_only: Vec<&str> = results
+        .iter()
+        .filter(|r| r.hog_faces > 0 && r.cnn_faces == 0)
+        .map(|r| r.image_path.as_str())
+        .collect();
+    let cnn_only: Vec<&str> = results
+        .iter()
+        .filter(|r| r.cnn_faces > 0 && r.hog_faces == 0)
+        .map(|r| r.image_path.as_str())
+        .collect();
+
+    let mut text = String::new();
+    let _ = writeln!(text, "Images: {}", results.len());
+    let _ = writeln!(
+        text,
+        "{:<9}{:>9}{:>9}{:>9}{:>9}{:>9}{:>8}",
+        "Detector", "mean ms", "p50 ms", "p95 ms", "min ms", "max ms", "faces"
+    );
+    for (name, latency, faces) in [
+        ("HOG", hog_latency, hog_faces),
+        ("CNN", cnn_latency, cnn_faces),
+    ] {
+        let _ = writeln!(
+            text,
+            "{:<9}{:>9.1}{:>9.1}{:>9.1}{:>9.1}{:>9.1}{:>8}",
+            name, latency.mean, latency.median, latency.p95, latency.min, latency.max, faces
+        );
+    }
+    let _ = writeln!(
+        text,
+        "Matched faces (IoU >= {}): {}, mean IoU {:.3}",
+        MATCH_IOU,
+        ious.len(),
+        mean_iou
+    );
+    let _ = writeln!(text, "Faces found only by HOG: {} images", hog_only.len());
+    for path in hog_only {
+        let _ = writeln!(text, "  {}", path);
+    }
+    let _ = writeln!(text, "Faces found only by CNN: {} images", cnn_only.len());
+    for path in cnn_only {
+        let _ = writeln!(text, "  {}", path);
+    }
+    text
+}
+
+pub fn write_benchmark_csv(results: &[ImageBenchmark], csv_path: &str) -> Result<(), AppError> {
+    let mut file = fs::File::create(csv_path)?;
+    writeln!(file, "imagePath,hogMs,cnnMs,hogFaces,cnnFaces,matchedFaces")?;
+
+    for result in results {
+        writeln!(
+            file,
+            "{},{:.3},{:.3},{},{},{}",
+            csv_field(&result.image_path),
+            result.hog_ms,
+            result.cnn_ms,
+            result.hog_faces,
+            result.cnn_faces,
+            result.matched_ious.len()
+        )?;
+    }
+    Ok(())
+}
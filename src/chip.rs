@@ -0,0 +1,215 @@
+use crate::archive::{for_each_entry, is_archive};
+use crate::detect::{Detection, Detectors, ImageInput};
+use crate::error::AppError;
+use crate::photos::index_photos_by_name;
+use crate::tool::{get_full_file_name, LANDMARK_COUNT};
+use dlib_face_recognition::Point;
+use image::{Rgb, RgbImage};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChipConfig {
+    // Width and height of the square chip in pixels
+    pub size: u32,
+    // Margin around the face on each side, as a fraction of the face width
+    pub padding: f64,
+}
+
+impl Default for ChipConfig {
+    fn default() -> Self {
+        ChipConfig {
+            size: 150,
+            padding: 0.25,
+        }
+    }
+}
+
+// Eye centres in an unpadded unit-square face, matching dlib's own chip layout closely
+const LEFT_EYE: (f64, f64) = (0.3, 0.4);
+const RIGHT_EYE: (f64, f64) = (0.7, 0.4);
+
+/// Cuts a square face chip rotated so the eyes are level, scaled so the eyes
+/// land at fixed positions. Needs the 68 dlib landmarks; areas outside the
+/// source photo are filled with black.
+pub fn extract_chip(
+    image: &RgbImage,
+    landmarks: &[Point],
+    config: &ChipConfig,
+) -> Result<RgbImage, String> {
+    if landmarks.len() != LANDMARK_COUNT {
+        return Err(format!(
+            "Expected {} landmarks, got {}",
+            LANDMARK_COUNT,
+            landmarks.len()
+        ));
+    }
+    // 36-41 is the eye on the image's left, 42-47 the one on its right
+    let left = centre(&landmarks[36..=41]);
+    let right = centre(&landmarks[42..=47]);
+
+    let size = config.size.max(1) as f64;
+    let span = 1.0 + 2.0 * config.padding;
+    let target = |(x, y): (f64, f64)| {
+        (
+            (config.padding + x) / span * size,
+            (config.padding + y) / span * size,
+        )
+    };
+    let (target_left, target_right) = (target(LEFT_EYE), target(RIGHT_EYE));
+
+    // Similarity transform from chip coordinates back to the source photo
+    let (dx, dy) = (right.0 - left.0, right.1 - left.1);
+    let source_distance = (dx * dx + dy * dy).sqrt();
+    if source_distance < f64::EPSILON {
+        return Err(String::from("Eye landmarks coincide"));
+    }
+    let scale = source_distance / (target_right.0 - target_left.0);
+    let (sin, cos) = dy.atan2(dx).sin_cos();
+
+    let mut chip = RgbImage::new(config.size.max(1), config.size.max(1));
+    for (u, v, pixel) in chip.enumerate_pixels_mut() {
+        let ox = (u as f64 + 0.5 - target_left.0) * scale;
+        let oy = (v as f64 + 0.5 - target_left.1) * scale;
+        let sx = left.0 + ox * cos - oy * sin;
+        let sy = left.1 + ox * sin + oy * cos;
+        *pixel = sample_bilinear(image, sx - 0.5, sy - 0.5);
+    }
+    Ok(chip)
+}
+
+// Chips for every detection with a full set of landmarks, in detection order
+pub fn extract_chips(
+    image: &RgbImage,
+    detections: &[Detection],
+    config: &ChipConfig,
+) -> Vec<RgbImage> {
+    detections
+        .iter()
+        .filter_map(|d| extract_chip(image, &d.landmarks, config).ok())
+        .collect()
+}
+
+/// Detects the faces in a photo and saves one chip per face into `output_dir`
+/// as `<photo stem>_chip_<n>.png`. Returns the paths written.
+pub fn save_chips(
+    photo_path: &str,
+    output_dir: &str,
+    detectors: &Detectors,
+    config: &ChipConfig,
+) -> Result<Vec<String>, AppError> {
+    let input = ImageInput::Path(photo_path);
+    let image = input.load()?;
+    let chips = extract_chips(&image, &detectors.detect(&image), config);
+    let stem = Path::new(photo_path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("face");
+
+    fs::create_dir_all(output_dir)?;
+    let mut paths = Vec::new();
+    for (i, chip) in chips.iter().enumerate() {
+        let path = Path::new(output_dir).join(format!("{}_chip_{}.png", stem, i));
+        chip.save(&path)?;
+        paths.push(path.to_string_lossy().into_owned());
+    }
+    Ok(paths)
+}
+
+/// Chip of the first face the detectors find, which is the face `Feature::from_image`
+/// encodes. Falls back to the whole photo scaled down when no chip can be made.
+pub fn face_thumbnail(
+    input: &ImageInput,
+    detectors: &Detectors,
+    size: u32,
+) -> Result<RgbImage, AppError> {
+    let image = input.load()?;
+    let config = ChipConfig {
+        size,
+        ..ChipConfig::default()
+    };
+
+    let chip = detectors
+        .detect(&image)
+        .first()
+        .and_then(|d| extract_chip(&image, &d.landmarks, &config).ok());
+    Ok(chip.unwrap_or_else(|| {
+        image::imageops::resize(&*image, size, size, image::imageops::FilterType::Triangle)
+    }))
+}
+
+/// Thumbnails of the named photos, keyed by file name, read from a photo folder
+/// or from a .zip or .tar(.gz) archive. Photos that are missing, fail to load or
+/// share their file name with another photo are left out.
+pub fn face_thumbnails(
+    photo_source: &str,
+    photo_file_names: &HashSet<String>,
+    detectors: &Detectors,
+    size: u32,
+) -> HashMap<String, RgbImage> {
+    let mut thumbnails = HashMap::new();
+
+    if is_archive(photo_source) {
+        let wanted = |name: &str| photo_file_names.contains(&get_full_file_name(name));
+        let mut seen = HashSet::new();
+        let mut ambiguous = HashSet::new();
+        let result = for_each_entry(photo_source, wanted, |entry| {
+            let name = get_full_file_name(&entry.name);
+            if !seen.insert(name.clone()) {
+                tracing::warn!(archive = photo_source, photo = %entry.name, "Duplicate photo file name");
+                ambiguous.insert(name);
+                return;
+            }
+            if let Ok(thumbnail) = face_thumbnail(&ImageInput::Bytes(&entry.bytes), detectors, size)
+            {
+                thumbnails.insert(name, thumbnail);
+            }
+        });
+        if let Err(e) = result {
+            tracing::warn!(archive = photo_source, error = %e, "Error reading archive");
+        }
+        // Same rule as directories: a name shared by several entries gets no thumbnail
+        thumbnails.retain(|name, _| !ambiguous.contains(name));
+        return thumbnails;
+    }
+
+    for (name, path) in index_photos_by_name(photo_source) {
+        if photo_file_names.contains(&name) {
+            if let Ok(thumbnail) = face_thumbnail(&ImageInput::Path(&path), detectors, size) {
+                thumbnails.insert(name, thumbnail);
+            }
+        }
+    }
+    thumbnails
+}
+
+fn centre(points: &[Point]) -> (f64, f64) {
+    let len = points.len() as f64;
+    let (x, y) = points
+        .iter()
+        .fold((0.0, 0.0), |(x, y), p| (x + p.x() as f64, y + p.y() as f64));
+    (x / len, y / len)
+}
+
+fn sample_bilinear(image: &RgbImage, x: f64, y: f64) -> Rgb<u8> {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let texel = |x: f64, y: f64| -> [f64; 3] {
+        if x < 0.0 || y < 0.0 || x >= image.width() as f64 || y >= image.height() as f64 {
+            return [0.0; 3];
+        }
+        let Rgb(channels) = *image.get_pixel(x as u32, y as u32);
+        channels.map(f64::from)
+    };
+    let (a, b) = (texel(x0, y0), texel(x0 + 1.0, y0));
+    let (c, d) = (texel(x0, y0 + 1.0), texel(x0 + 1.0, y0 + 1.0));
+
+    let mut out = [0u8; 3];
+    for i in 0..3 {
+        let top = a[i] * (1.0 - fx) + b[i] * fx;
+        let bottom = c[i] * (1.0 - fx) + d[i] * fx;
+        out[i] = (top * (1.0 - fy) + bottom * fy).round().clamp(0.0, 255.0) as u8;
+    }
+    Rgb(out)
+}
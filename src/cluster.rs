@@ -0,0 +1,70 @@
+use crate::compare::euclidean_distance;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Chinese-whispers graph clustering. Two vectors are linked when their distance
+/// is within `threshold`; every vector then repeatedly adopts the most common
+/// label among its neighbours. Returns one cluster label per input vector,
+/// numbered from 0 in order of first appearance.
+pub fn chinese_whispers(vectors: &[Vec<f64>], threshold: f64, iterations: usize) -> Vec<usize> {
+    let n = vectors.len();
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); n];
+
+    for i in 0..n {
+        for j in (i + 1)..n {
+            if euclidean_distance(&vectors[i], &vectors[j]) <= threshold {
+                neighbours[i].push(j);
+                neighbours[j].push(i);
+            }
+        }
+    }
+
+    let mut labels: Vec<usize> = (0..n).collect();
+    for _ in 0..iterations {
+        let mut changed = false;
+        for i in 0..n {
+            if neighbours[i].is_empty() {
+                continue;
+            }
+            let mut votes: HashMap<usize, usize> = HashMap::new();
+            for &j in &neighbours[i] {
+                *votes.entry(labels[j]).or_insert(0) += 1;
+            }
+            // Highest vote wins, ties go to the smallest label so runs are repeatable
+            let best = votes
+                .iter()
+                .max_by_key(|(label, count)| (**count, Reverse(**label)))
+                .map(|(label, _)| *label)
+                .unwrap_or(labels[i]);
+            if best != labels[i] {
+                labels[i] = best;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut compact: HashMap<usize, usize> = HashMap::new();
+    labels
+        .iter()
+        .map(|label| {
+            let next = compact.len();
+            *compact.entry(*label).or_insert(next)
+        })
+        .collect()
+}
+
+/// Groups items by the labels returned from `chinese_whispers`, largest group first.
+pub fn group_by_label<T: Clone>(items: &[T], labels: &[usize]) -> Vec<Vec<T>> {
+    let num_clusters = labels.iter().max().map(|m| m + 1).unwrap_or(0);
+    let mut groups: Vec<Vec<T>> = vec![Vec::new(); num_clusters];
+
+    for (item, &label) in items.iter().zip(labels.iter()) {
+        groups[label].push(item.clone());
+    }
+
+    groups.sort_by_key(|group| Reverse(group.len()));
+    groups
+}
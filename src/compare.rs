@@ -1,6 +1,20 @@
-use crate::dbs::get_features_by_child_id;
+use crate::cluster::{chinese_whispers, group_by_label};
+use crate::dbs::{get_all_child_ids, get_features_by_child_id};
+use crate::error::StatsError;
+use crate::photos::CAPTURE_DATE_FORMAT;
+use crate::stats::{
+    compute_average, compute_median, compute_medoid, compute_prototypes, compute_robust_average,
+    compute_weighted_average, nearest_centre, RobustConfig,
+};
+use chrono::NaiveDateTime;
 use dlib_face_recognition::*;
 use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+// Prototypes computed per child when none are stored
+pub const DEFAULT_PROTOTYPE_COUNT: usize = 3;
+// Photos taken this many days apart weigh half as much in a recency-weighted average
+pub const DEFAULT_HALF_LIFE_DAYS: f64 = 365.0;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FaceEncoding {
@@ -9,12 +23,59 @@ pub struct FaceEncoding {
     pub feature_vector: Vec<f64>,
     pub photo_file_name: String,
     pub f_type: String,
+    // Insertion time of the row; see `capture_date` for when the photo was taken
     pub timestamp: String,
+    pub capture_date: Option<String>,
+    // Archive the photo was read from; None for loose photo files and aggregates
+    pub origin: Option<String>,
+    // Path of the photo file, or its entry name inside `origin`; None for aggregates
+    pub photo_path: Option<String>,
+}
+impl FaceEncoding {
+    // An aggregate computed in memory; it has no database row yet
+    pub fn aggregate(
+        child_id: &str,
+        photo_file_name: &str,
+        f_type: &str,
+        feature_vector: Vec<f64>,
+    ) -> Self {
+        FaceEncoding {
+            id: 0,
+            child_id: child_id.to_owned(),
+            feature_vector,
+            photo_file_name: photo_file_name.to_owned(),
+            f_type: f_type.to_owned(),
+            timestamp: String::new(),
+            capture_date: None,
+            origin: None,
+            photo_path: None,
+        }
+    }
+    pub fn capture_time(&self) -> Option<NaiveDateTime> {
+        self.capture_date
+            .as_deref()
+            .and_then(|date| NaiveDateTime::parse_from_str(date, CAPTURE_DATE_FORMAT).ok())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reference {
+    Average,
+    Median,
+    Medoid,
+    Robust,
 }
+
 pub struct FeatureSet {
     pub atomics: Vec<FaceEncoding>,
     pub average: FaceEncoding,
     pub median: FaceEncoding,
+    // The atomic closest to all the others, i.e. the child's most typical photo
+    pub medoid: FaceEncoding,
+    // Centroid of the atomics with outliers excluded
+    pub robust: FaceEncoding,
+    // k-means centres over the atomics, covering different ages and appearances
+    pub prototypes: Vec<FaceEncoding>,
 }
 
 impl FeatureSet {
@@ -27,6 +88,58 @@ impl FeatureSet {
             Err(e) => return Err(e.to_string()),
         }
     }
+    /// Builds a feature set from atomics alone, computing the aggregates instead
+    /// of reading the stored Average/Median rows.
+    pub fn from_atomics(child_id: &str, atomics: Vec<FaceEncoding>) -> Result<Self, String> {
+        if atomics.is_empty() {
+            return Err(format!("No atomics found for child ID {}", child_id));
+        }
+        let vectors: Vec<Vec<f64>> = atomics.iter().map(|a| a.feature_vector.clone()).collect();
+        let to_string = |e: StatsError| format!("Child ID {}: {}", child_id, e);
+
+        let average_vector = compute_average(&vectors).map_err(to_string)?;
+        let average = FaceEncoding::aggregate(child_id, "average", "Average", average_vector);
+        let median_vector = compute_median(&vectors).map_err(to_string)?;
+        let median = FaceEncoding::aggregate(child_id, "median", "Median", median_vector);
+        let medoid_atomic = &atomics[compute_medoid(&vectors).map_err(to_string)?];
+        let medoid = FaceEncoding::aggregate(
+            child_id,
+            &medoid_atomic.photo_file_name,
+            "Medoid",
+            medoid_atomic.feature_vector.clone(),
+        );
+        let robust = FaceEncoding::aggregate(
+            child_id,
+            "robust",
+            "Robust",
+            compute_robust_average(&vectors, &RobustConfig::default()).map_err(to_string)?,
+        );
+        let prototypes = compute_prototypes(&vectors, DEFAULT_PROTOTYPE_COUNT, 100)
+            .map_err(to_string)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| {
+                FaceEncoding::aggregate(child_id, &format!("prototype_{}", i), "Prototype", p)
+            })
+            .collect();
+
+        Ok(FeatureSet {
+            atomics,
+            average,
+            median,
+            medoid,
+            robust,
+            prototypes,
+        })
+    }
+    pub fn reference(&self, reference: Reference) -> &FaceEncoding {
+        match reference {
+            Reference::Average => &self.average,
+            Reference::Median => &self.median,
+            Reference::Medoid => &self.medoid,
+            Reference::Robust => &self.robust,
+        }
+    }
     fn find_distant_atomics(
         &self,
         threshold: f64,
@@ -55,8 +168,181 @@ impl FeatureSet {
         let reference_vector = &self.median.feature_vector;
         return self.find_distant_atomics(threshold, reference_vector);
     }
+    pub fn find_distant_atomics_from_medoid(&self, threshold: f64) -> Vec<FaceEncoding> {
+        let reference_vector = &self.medoid.feature_vector;
+        return self.find_distant_atomics(threshold, reference_vector);
+    }
+    pub fn find_distant_atomics_from_robust(&self, threshold: f64) -> Vec<FaceEncoding> {
+        let reference_vector = &self.robust.feature_vector;
+        return self.find_distant_atomics(threshold, reference_vector);
+    }
+    // Distance to the closest prototype, falling back to the average when none exist
+    pub fn distance_to_nearest_prototype(&self, feature_vector: &[f64]) -> f64 {
+        if self.prototypes.is_empty() {
+            return euclidean_distance(feature_vector, &self.average.feature_vector);
+        }
+        let centres: Vec<Vec<f64>> = self
+            .prototypes
+            .iter()
+            .map(|p| p.feature_vector.clone())
+            .collect();
+        nearest_centre(feature_vector, &centres).1
+    }
+    pub fn find_distant_atomics_from_prototypes(&self, threshold: f64) -> Vec<FaceEncoding> {
+        self.atomics
+            .iter()
+            .filter(|atomic| self.distance_to_nearest_prototype(&atomic.feature_vector) > threshold)
+            .cloned()
+            .collect()
+    }
+    /// Average weighted by how close each atomic's capture date is to `as_of`;
+    /// an atomic `half_life_days` away counts half as much as one taken that day.
+    /// Undated atomics are ignored; when none are dated, or every weight rounds
+    /// to zero, this is the plain average.
+    pub fn recency_weighted_average(&self, as_of: NaiveDateTime, half_life_days: f64) -> Vec<f64> {
+        self.recent_reference(Some(as_of), half_life_days, None)
+            .unwrap_or_else(|| self.average.feature_vector.clone())
+    }
+    // Recency-weighted average of every atomic but `excluded`, falling back to
+    // their plain average when `as_of` is unknown or no dated atomic is left.
+    // None when no other atomic exists.
+    fn recent_reference(
+        &self,
+        as_of: Option<NaiveDateTime>,
+        half_life_days: f64,
+        excluded: Option<i32>,
+    ) -> Option<Vec<f64>> {
+        let others: Vec<&FaceEncoding> = self
+            .atomics
+            .iter()
+            .filter(|a| Some(a.id) != excluded)
+            .collect();
+
+        if let Some(as_of) = as_of {
+            let (vectors, weights): (Vec<Vec<f64>>, Vec<f64>) = others
+                .iter()
+                .filter_map(|a| {
+                    a.capture_time().map(|t| {
+                        let age_days = (as_of - t).num_seconds().abs() as f64 / 86_400.0;
+                        (
+                            a.feature_vector.clone(),
+                            0.5_f64.powf(age_days / half_life_days),
+                        )
+                    })
+                })
+                .unzip();
+            if let Ok(weighted) = compute_weighted_average(&vectors, &weights) {
+                return Some(weighted);
+            }
+        }
+        let vectors: Vec<Vec<f64>> = others.iter().map(|a| a.feature_vector.clone()).collect();
+        compute_average(&vectors).ok()
+    }
+    // Outliers judged against the child's appearance around each photo's own
+    // capture date. The photo itself is left out of its reference, so a lone
+    // recent photo is compared with the others rather than with itself.
+    pub fn find_distant_atomics_from_recent(
+        &self,
+        threshold: f64,
+        half_life_days: f64,
+    ) -> Vec<FaceEncoding> {
+        self.atomics
+            .iter()
+            .filter(|atomic| {
+                self.recent_reference(atomic.capture_time(), half_life_days, Some(atomic.id))
+                    .is_some_and(|reference_vector| {
+                        euclidean_distance(&atomic.feature_vector, &reference_vector) > threshold
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+    /// Clusters the atomics and returns the clusters when they split into two or
+    /// more well-separated groups (likely two children filed under one ID).
+    /// Clusters smaller than `min_cluster_size` are ignored; an empty result
+    /// means the atomics look like a single identity.
+    pub fn find_identity_clusters(
+        &self,
+        threshold: f64,
+        min_cluster_size: usize,
+    ) -> Vec<Vec<FaceEncoding>> {
+        let vectors: Vec<Vec<f64>> = self
+            .atomics
+            .iter()
+            .map(|atomic| atomic.feature_vector.clone())
+            .collect();
+        let labels = chinese_whispers(&vectors, threshold, 100);
+
+        let clusters: Vec<Vec<FaceEncoding>> = group_by_label(&self.atomics, &labels)
+            .into_iter()
+            .filter(|cluster| cluster.len() >= min_cluster_size)
+            .collect();
+
+        let centroids: Vec<Vec<f64>> = clusters
+            .iter()
+            .filter_map(|cluster| {
+                let vectors: Vec<Vec<f64>> =
+                    cluster.iter().map(|e| e.feature_vector.clone()).collect();
+                compute_average(&vectors).ok()
+            })
+            .collect();
+
+        let separated = centroids.iter().enumerate().any(|(i, a)| {
+            centroids[i + 1..]
+                .iter()
+                .any(|b| euclidean_distance(a, b) > threshold)
+        });
+
+        if separated {
+            clusters
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Reference encodings of every enrolled child, used to identify unknown faces.
+pub struct Gallery {
+    pub feature_sets: Vec<FeatureSet>,
+    // Children whose rows could not be loaded; they take no part in matching
+    pub skipped: Vec<String>,
+}
+
+impl Gallery {
+    pub fn from_db_table(db_path: &str) -> Result<Self, String> {
+        let child_ids = get_all_child_ids(db_path).map_err(|e| e.to_string())?;
+        let mut feature_sets = Vec::new();
+        let mut skipped = Vec::new();
+        for id in child_ids {
+            match FeatureSet::from_db_table(db_path, &id) {
+                Ok(feature_set) => feature_sets.push(feature_set),
+                Err(e) => {
+                    warn!(child_id = %id, error = %e, "Skipping child");
+                    skipped.push(id);
+                }
+            }
+        }
+
+        Ok(Gallery {
+            feature_sets,
+            skipped,
+        })
+    }
+    /// Returns the child with the closest prototype to `feature_vector`, with the distance.
+    pub fn nearest(&self, feature_vector: &[f64]) -> Option<(&FeatureSet, f64)> {
+        self.feature_sets
+            .iter()
+            .map(|fs| (fs, fs.distance_to_nearest_prototype(feature_vector)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+    /// Like `nearest`, but only returns a match within `threshold`.
+    pub fn identify(&self, feature_vector: &[f64], threshold: f64) -> Option<(String, f64)> {
+        self.nearest(feature_vector)
+            .filter(|(_, distance)| *distance <= threshold)
+            .map(|(fs, distance)| (fs.average.child_id.clone(), distance))
+    }
 }
-fn euclidean_distance(vec1: &[f64], vec2: &[f64]) -> f64 {
+pub fn euclidean_distance(vec1: &[f64], vec2: &[f64]) -> f64 {
     vec1.iter()
         .zip(vec2.iter())
         .map(|(a, b)| (a - b).powi(2))
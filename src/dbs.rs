@@ -1,9 +1,12 @@
-use crate::compare::{FaceEncoding, FeatureSet};
+use crate::compare::{FaceEncoding, FeatureSet, DEFAULT_PROTOTYPE_COUNT};
 use crate::error::*;
+use crate::stats::{
+    compute_average, compute_median, compute_medoid, compute_prototypes, compute_robust_average,
+    OnlineStats, RobustConfig,
+};
 use bincode; // For serialization
-use rusqlite::{params, Connection, Result};
+use rusqlite::{params, Connection, OptionalExtension, Result, Row};
 use serde::{Deserialize, Serialize};
-use std::io;
 
 pub fn create_face_encodings_table(db_path: &str) -> Result<()> {
     let conn = Connection::open(db_path)?;
@@ -15,43 +18,127 @@ pub fn create_face_encodings_table(db_path: &str) -> Result<()> {
             featureVector BLOB NOT NULL,
             photoFileName TEXT,
             type TEXT,
-            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+            captureDate DATETIME,
+            photoOrigin TEXT,
+            photoPath TEXT
         );",
         [],
     )?;
+    // Tables created before capture dates were tracked lack the column
+    add_column_if_missing(&conn, "FaceEncodings", "captureDate", "DATETIME")?;
+    // Likewise for the archive a photo was read from, and the photo's own path
+    add_column_if_missing(&conn, "FaceEncodings", "photoOrigin", "TEXT")?;
+    add_column_if_missing(&conn, "FaceEncodings", "photoPath", "TEXT")?;
 
     Ok(())
 }
 
-pub fn insert_face_encoding(
-    db_path: &str,
-    child_id: &str,
-    feature_vector: &Vec<f64>,
-    photo_file_name: &str,
-    f_type: &str,
-) -> Result<()> {
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<String>>>()?;
+
+    if !columns.iter().any(|c| c == column) {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, decl),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn insert_face_encoding(db_path: &str, encoding: &FaceEncoding) -> Result<()> {
     let conn = Connection::open(db_path)?;
+    insert_with(&conn, encoding)
+}
 
+// Stores everything but the id and timestamp, which SQLite assigns
+fn insert_with(conn: &Connection, encoding: &FaceEncoding) -> Result<()> {
     // Serialize the Vec<f64> into a byte array
-    let serialized_feature_vector = match bincode::serialize(feature_vector) {
+    let serialized_feature_vector = match bincode::serialize(&encoding.feature_vector) {
         Ok(vec) => vec,
         Err(e) => return Err(rusqlite::Error::ExecuteReturnedResults), // Or handle differently
     };
 
     conn.execute(
-        "INSERT INTO FaceEncodings (childID, featureVector, photoFileName, type) VALUES (?1, ?2, ?3, ?4)",
-        params![child_id, serialized_feature_vector, photo_file_name, f_type],
+        "INSERT INTO FaceEncodings (childID, featureVector, photoFileName, type, captureDate, photoOrigin, photoPath) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            encoding.child_id,
+            serialized_feature_vector,
+            encoding.photo_file_name,
+            encoding.f_type,
+            encoding.capture_date,
+            encoding.origin,
+            encoding.photo_path
+        ],
     )?;
 
     Ok(())
 }
 
-pub fn get_features_by_child_id(db_path: &str, child_id: &str) -> Result<FeatureSet, AppError> {
+// Maps a row selected as: id, childID, featureVector, photoFileName, type, timestamp, captureDate,
+// photoOrigin, photoPath
+fn face_encoding_from_row(row: &Row) -> Result<FaceEncoding> {
+    let feature_vector_blob: Vec<u8> = row.get(2)?;
+
+    let feature_vector = match bincode::deserialize(&feature_vector_blob) {
+        Ok(vec) => vec,
+        Err(_) => return Err(rusqlite::Error::InvalidQuery), // Using a generic error
+    };
+
+    Ok(FaceEncoding {
+        id: row.get(0)?,
+        child_id: row.get(1)?,
+        feature_vector,
+        photo_file_name: row.get(3)?,
+        f_type: row.get(4)?,
+        timestamp: row.get(5)?,
+        capture_date: row.get(6)?,
+        origin: row.get(7)?,
+        photo_path: row.get(8)?,
+    })
+}
+
+pub fn get_face_encoding_by_id(db_path: &str, id: i32) -> Result<Option<FaceEncoding>, AppError> {
+    let conn = Connection::open(db_path).map_err(AppError::Sqlite)?;
+
+    conn.query_row(
+        "SELECT id, childID, featureVector, photoFileName, type, timestamp, captureDate, photoOrigin,
+                photoPath
+         FROM FaceEncodings
+         WHERE id = ?1",
+        params![id],
+        face_encoding_from_row,
+    )
+    .optional()
+    .map_err(AppError::Sqlite)
+}
+
+pub fn get_all_child_ids(db_path: &str) -> Result<Vec<String>, AppError> {
     let conn = Connection::open(db_path).map_err(AppError::Sqlite)?;
 
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT childID FROM FaceEncodings ORDER BY childID")
+        .map_err(AppError::Sqlite)?;
+
+    let child_ids = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(AppError::Sqlite)?
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(AppError::Sqlite)?;
+
+    Ok(child_ids)
+}
+
+pub fn get_features_by_child_id(db_path: &str, child_id: &str) -> Result<FeatureSet, AppError> {
+    let mut conn = Connection::open(db_path).map_err(AppError::Sqlite)?;
+
     let mut stmt = conn
         .prepare(
-            "SELECT id, childID, featureVector, photoFileName, type, timestamp
+            "SELECT id, childID, featureVector, photoFileName, type, timestamp, captureDate, photoOrigin,
+                photoPath
          FROM FaceEncodings
          WHERE childID = ?1",
         )
@@ -60,25 +147,12 @@ pub fn get_features_by_child_id(db_path: &str, child_id: &str) -> Result<Feature
     let mut atomics: Vec<FaceEncoding> = Vec::new();
     let mut average: Option<FaceEncoding> = None;
     let mut median: Option<FaceEncoding> = None;
+    let mut medoid: Option<FaceEncoding> = None;
+    let mut robust: Option<FaceEncoding> = None;
+    let mut prototypes: Vec<FaceEncoding> = Vec::new();
 
     let face_encoding_iter = stmt
-        .query_map(params![child_id], |row| {
-            let feature_vector_blob: Vec<u8> = row.get(2)?;
-
-            let feature_vector = match bincode::deserialize(&feature_vector_blob) {
-                Ok(vec) => vec,
-                Err(_) => return Err(rusqlite::Error::InvalidQuery), // Using a generic error
-            };
-
-            Ok(FaceEncoding {
-                id: row.get(0)?,
-                child_id: row.get(1)?,
-                feature_vector,
-                photo_file_name: row.get(3)?,
-                f_type: row.get(4)?,
-                timestamp: row.get(5)?,
-            })
-        })
+        .query_map(params![child_id], face_encoding_from_row)
         .map_err(AppError::Sqlite)?;
 
     for encoding in face_encoding_iter {
@@ -87,18 +161,284 @@ pub fn get_features_by_child_id(db_path: &str, child_id: &str) -> Result<Feature
             "Atomic" => atomics.push(encoding),
             "Average" => average = Some(encoding),
             "Median" => median = Some(encoding),
+            "Medoid" => medoid = Some(encoding),
+            "Robust" => robust = Some(encoding),
+            "Prototype" => prototypes.push(encoding),
             _ => {}
         }
     }
 
-    let average = average
-        .ok_or_else(|| AppError::Io(io::Error::new(io::ErrorKind::NotFound, "No average found")))?;
-    let median = median
-        .ok_or_else(|| AppError::Io(io::Error::new(io::ErrorKind::NotFound, "No median found")))?;
+    drop(stmt);
+
+    // Aggregates are written last, so an interrupted run, a manual delete or an
+    // incremental update can leave some rows missing. Only those are computed,
+    // and they are stored so later loads read them back.
+    let vectors: Vec<Vec<f64>> = atomics.iter().map(|a| a.feature_vector.clone()).collect();
+    let mut computed: Vec<FaceEncoding> = Vec::new();
+    let mut fill = |row: Option<FaceEncoding>,
+                    compute: &dyn Fn() -> Result<FaceEncoding, StatsError>|
+     -> Result<FaceEncoding, AppError> {
+        match row {
+            Some(row) => Ok(row),
+            None => {
+                let row = compute()?;
+                computed.push(row.clone());
+                Ok(row)
+            }
+        }
+    };
+    let average = fill(average, &|| {
+        let vector = compute_average(&vectors)?;
+        Ok(FaceEncoding::aggregate(
+            child_id, "average", "Average", vector,
+        ))
+    })?;
+    let median = fill(median, &|| {
+        let vector = compute_median(&vectors)?;
+        Ok(FaceEncoding::aggregate(
+            child_id, "median", "Median", vector,
+        ))
+    })?;
+    let medoid = fill(medoid, &|| {
+        let atomic = &atomics[compute_medoid(&vectors)?];
+        Ok(FaceEncoding::aggregate(
+            child_id,
+            &atomic.photo_file_name,
+            "Medoid",
+            atomic.feature_vector.clone(),
+        ))
+    })?;
+    let robust = fill(robust, &|| {
+        let vector = compute_robust_average(&vectors, &RobustConfig::default())?;
+        Ok(FaceEncoding::aggregate(
+            child_id, "robust", "Robust", vector,
+        ))
+    })?;
+    if prototypes.is_empty() {
+        prototypes = compute_prototypes(&vectors, DEFAULT_PROTOTYPE_COUNT, 100)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| {
+                FaceEncoding::aggregate(child_id, &format!("prototype_{}", i), "Prototype", p)
+            })
+            .collect();
+        computed.extend(prototypes.iter().cloned());
+    }
+
+    if !computed.is_empty() {
+        let tx = conn.transaction().map_err(AppError::Sqlite)?;
+        for row in &computed {
+            insert_with(&tx, row).map_err(AppError::Sqlite)?;
+        }
+        tx.commit().map_err(AppError::Sqlite)?;
+    }
 
     Ok(FeatureSet {
         atomics,
         average,
         median,
+        medoid,
+        robust,
+        prototypes,
     })
 }
+
+// IDs of a child's atomics extracted from the photo at `photo_path`. Rows of loose
+// photos stored before photo paths were recorded are matched on the file name instead.
+pub fn get_atomic_ids_by_photo(
+    db_path: &str,
+    child_id: &str,
+    photo_path: &str,
+    photo_file_name: &str,
+) -> Result<Vec<i32>> {
+    let conn = Connection::open(db_path)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id FROM FaceEncodings WHERE childID = ?1 AND type = 'Atomic'
+         AND (photoPath = ?2
+              OR (photoPath IS NULL AND photoOrigin IS NULL AND photoFileName = ?3))",
+    )?;
+    let ids = stmt
+        .query_map(params![child_id, photo_path, photo_file_name], |row| {
+            row.get(0)
+        })?
+        .collect();
+    ids
+}
+
+pub fn delete_aggregates(db_path: &str, child_id: &str) -> Result<()> {
+    let conn = Connection::open(db_path)?;
+
+    conn.execute(
+        "DELETE FROM FaceEncodings WHERE childID = ?1 AND type != 'Atomic'",
+        params![child_id],
+    )?;
+
+    Ok(())
+}
+
+// Swap every stored aggregate row of a child for `aggregates`, all or nothing
+pub fn replace_aggregates(
+    db_path: &str,
+    child_id: &str,
+    aggregates: &[FaceEncoding],
+) -> Result<()> {
+    let mut conn = Connection::open(db_path)?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "DELETE FROM FaceEncodings WHERE childID = ?1 AND type != 'Atomic'",
+        params![child_id],
+    )?;
+    for aggregate in aggregates {
+        insert_with(&tx, aggregate)?;
+    }
+    tx.commit()
+}
+
+pub fn create_child_aggregates_table(db_path: &str) -> Result<()> {
+    let conn = Connection::open(db_path)?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ChildAggregates (
+            childID TEXT PRIMARY KEY,
+            onlineStats BLOB NOT NULL,
+            timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
+        );",
+        [],
+    )?;
+
+    Ok(())
+}
+
+fn read_online_stats(conn: &Connection, child_id: &str) -> Result<Option<OnlineStats>, AppError> {
+    let blob: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT onlineStats FROM ChildAggregates WHERE childID = ?1",
+            params![child_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(AppError::Sqlite)?;
+
+    match blob {
+        Some(blob) => Ok(Some(bincode::deserialize(&blob)?)),
+        None => Ok(None),
+    }
+}
+
+pub fn save_online_stats(
+    db_path: &str,
+    child_id: &str,
+    online_stats: &OnlineStats,
+) -> Result<(), AppError> {
+    let conn = Connection::open(db_path).map_err(AppError::Sqlite)?;
+    write_online_stats(&conn, child_id, online_stats)
+}
+
+fn write_online_stats(
+    conn: &Connection,
+    child_id: &str,
+    online_stats: &OnlineStats,
+) -> Result<(), AppError> {
+    let blob = bincode::serialize(online_stats)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO ChildAggregates (childID, onlineStats) VALUES (?1, ?2)",
+        params![child_id, blob],
+    )
+    .map_err(AppError::Sqlite)?;
+
+    Ok(())
+}
+
+/// Deletes the atomics in `removed_ids` and inserts `added` for one child, and
+/// applies the same changes to the child's online statistics, all in one
+/// transaction. Average and Median are rewritten from the statistics; Medoid,
+/// Robust and Prototype rows can't be updated incrementally, so they are dropped
+/// and recomputed by the next `get_features_by_child_id`. Statistics are seeded
+/// from the stored atomics the first time a child is touched.
+pub fn update_atomics(
+    db_path: &str,
+    child_id: &str,
+    added: &[FaceEncoding],
+    removed_ids: &[i32],
+) -> Result<(), AppError> {
+    create_child_aggregates_table(db_path).map_err(AppError::Sqlite)?;
+    let mut conn = Connection::open(db_path).map_err(AppError::Sqlite)?;
+    let tx = conn.transaction().map_err(AppError::Sqlite)?;
+
+    let mut removed: Vec<Vec<f64>> = Vec::new();
+    for id in removed_ids {
+        let blob: Vec<u8> = tx
+            .query_row(
+                "SELECT featureVector FROM FaceEncodings
+                 WHERE id = ?1 AND childID = ?2 AND type = 'Atomic'",
+                params![id, child_id],
+                |row| row.get(0),
+            )
+            .map_err(AppError::Sqlite)?;
+        removed.push(bincode::deserialize(&blob)?);
+        tx.execute("DELETE FROM FaceEncodings WHERE id = ?1", params![id])
+            .map_err(AppError::Sqlite)?;
+    }
+    for atomic in added {
+        insert_with(&tx, atomic).map_err(AppError::Sqlite)?;
+    }
+
+    let online_stats = match read_online_stats(&tx, child_id)? {
+        Some(mut online_stats) => {
+            for vector in &removed {
+                online_stats.remove(vector)?;
+            }
+            for atomic in added {
+                online_stats.add(&atomic.feature_vector)?;
+            }
+            Some(online_stats)
+        }
+        // The atomic rows already include the changes, so seeding from them is enough
+        None => {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT featureVector FROM FaceEncodings WHERE childID = ?1 AND type = 'Atomic'",
+                )
+                .map_err(AppError::Sqlite)?;
+            let blobs = stmt
+                .query_map(params![child_id], |row| row.get::<_, Vec<u8>>(0))
+                .map_err(AppError::Sqlite)?
+                .collect::<Result<Vec<Vec<u8>>>>()
+                .map_err(AppError::Sqlite)?;
+            let vectors = blobs
+                .iter()
+                .map(|blob| bincode::deserialize(blob).map_err(AppError::Bincode))
+                .collect::<Result<Vec<Vec<f64>>, AppError>>()?;
+            if vectors.is_empty() {
+                None
+            } else {
+                Some(OnlineStats::from_features(&vectors)?)
+            }
+        }
+    };
+
+    tx.execute(
+        "DELETE FROM FaceEncodings WHERE childID = ?1 AND type != 'Atomic'",
+        params![child_id],
+    )
+    .map_err(AppError::Sqlite)?;
+    if let Some(online_stats) = online_stats.filter(|stats| stats.count > 0) {
+        write_online_stats(&tx, child_id, &online_stats)?;
+        let average =
+            FaceEncoding::aggregate(child_id, "average", "Average", online_stats.mean.clone());
+        insert_with(&tx, &average).map_err(AppError::Sqlite)?;
+        let median =
+            FaceEncoding::aggregate(child_id, "median", "Median", online_stats.median.clone());
+        insert_with(&tx, &median).map_err(AppError::Sqlite)?;
+    } else {
+        tx.execute(
+            "DELETE FROM ChildAggregates WHERE childID = ?1",
+            params![child_id],
+        )
+        .map_err(AppError::Sqlite)?;
+    }
+    tx.commit().map_err(AppError::Sqlite)
+}
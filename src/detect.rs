@@ -1,55 +1,224 @@
 use clap::Parser;
 use dlib_face_recognition::*;
 use image::*;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
 
+use crate::error::AppError;
+use crate::photos::list_image_files;
 use crate::tool::*;
 
-pub fn detect(input_photo_path: &str, output_photo_path: &str) {
-    let mut image = image::open(input_photo_path).unwrap().to_rgb8();
-    let matrix = ImageMatrix::from_image(&image);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetectorSource {
+    Hog,
+    Cnn,
+    // Drawn or corrected in annotation software and imported back
+    Manual,
+}
 
-    let detector = FaceDetector::default();
+#[derive(Debug, Clone)]
+pub struct Detection {
+    pub rect: Rectangle,
+    pub source: DetectorSource,
+    // The 68 dlib landmarks, in the predictor's order
+    pub landmarks: Vec<Point>,
+}
 
-    let Ok(cnn_detector) = FaceDetectorCnn::default() else {
-        panic!("Unable to load cnn face detector!");
-    };
+pub enum ImageInput<'a> {
+    Path(&'a str),
+    // Encoded file contents, e.g. a JPEG received over the network
+    Bytes(&'a [u8]),
+    Image(&'a RgbImage),
+}
 
-    let Ok(landmarks) = LandmarkPredictor::default() else {
-        panic!("Unable to load landmark predictor!");
-    };
+impl ImageInput<'_> {
+    pub fn load(&self) -> Result<Cow<'_, RgbImage>, AppError> {
+        match self {
+            ImageInput::Path(path) => Ok(Cow::Owned(image::open(path)?.to_rgb8())),
+            ImageInput::Bytes(bytes) => Ok(Cow::Owned(image::load_from_memory(bytes)?.to_rgb8())),
+            ImageInput::Image(image) => Ok(Cow::Borrowed(*image)),
+        }
+    }
+}
 
-    let red = Rgb([255, 0, 0]);
-    let green = Rgb([0, 255, 0]);
+/// Loaded detection models, so they can be reused across many images.
+pub struct Detectors {
+    hog: Option<FaceDetector>,
+    cnn: Option<FaceDetectorCnn>,
+    landmarks: LandmarkPredictor,
+}
 
-    let face_locations = tick("FaceDetector", || detector.face_locations(&matrix));
+impl Detectors {
+    // Loads the landmark predictor and the requested detectors
+    pub fn load(sources: &[DetectorSource]) -> Result<Self, AppError> {
+        let cnn = if sources.contains(&DetectorSource::Cnn) {
+            Some(FaceDetectorCnn::default().map_err(AppError::Model)?)
+        } else {
+            None
+        };
+        Ok(Detectors {
+            hog: sources
+                .contains(&DetectorSource::Hog)
+                .then(FaceDetector::default),
+            cnn,
+            landmarks: LandmarkPredictor::default().map_err(AppError::Model)?,
+        })
+    }
+    /// Runs every loaded detector over the image; HOG detections come first.
+    pub fn detect(&self, image: &RgbImage) -> Vec<Detection> {
+        let matrix = ImageMatrix::from_image(image);
+        let mut detections = Vec::new();
 
-    for r in face_locations.iter() {
-        draw_rectangle(&mut image, &r, red);
+        if let Some(hog) = &self.hog {
+            detections.extend(self.with_landmarks(&matrix, hog, DetectorSource::Hog));
+        }
+        if let Some(cnn) = &self.cnn {
+            detections.extend(self.with_landmarks(&matrix, cnn, DetectorSource::Cnn));
+        }
+        detections
+    }
+    fn with_landmarks(
+        &self,
+        matrix: &ImageMatrix,
+        detector: &dyn FaceDetectorTrait,
+        source: DetectorSource,
+    ) -> Vec<Detection> {
+        let face_locations = tracing::info_span!("detect", detector = ?source)
+            .in_scope(|| detector.face_locations(matrix));
+        tracing::info_span!("landmark").in_scope(|| {
+            face_locations
+                .iter()
+                .map(|rect| Detection {
+                    rect: *rect,
+                    source,
+                    landmarks: self.landmarks.face_landmarks(matrix, rect).to_vec(),
+                })
+                .collect()
+        })
+    }
+}
 
-        let landmarks = landmarks.face_landmarks(&matrix, &r);
+pub fn detect_faces(input: ImageInput, detectors: &Detectors) -> Result<Vec<Detection>, AppError> {
+    let image = input.load()?;
+    Ok(detectors.detect(&image))
+}
 
-        for point in landmarks.iter() {
-            draw_point(&mut image, &point, red);
-        }
+// Draws HOG detections in red, CNN detections in green and manual boxes in blue,
+// with lines `thickness` pixels wide
+pub fn annotate(image: &mut RgbImage, detections: &[Detection], thickness: u32) {
+    let red = Rgb([255, 0, 0]);
+    let green = Rgb([0, 255, 0]);
+    let blue = Rgb([0, 0, 255]);
+
+    for detection in detections {
+        let colour = match detection.source {
+            DetectorSource::Hog => red,
+            DetectorSource::Cnn => green,
+            DetectorSource::Manual => blue,
+        };
+        draw_rectangle_thick(image, &detection.rect, colour, thickness);
+        draw_landmark_contours(image, &detection.landmarks, colour, thickness);
     }
+}
 
-    let face_locations = tick("FaceDetectorCnn", || cnn_detector.face_locations(&matrix));
+// Runs both detectors and saves an annotated copy; returns the detections
+pub fn detect(input_photo_path: &str, output_photo_path: &str) -> Result<Vec<Detection>, AppError> {
+    let detectors = Detectors::load(&[DetectorSource::Hog, DetectorSource::Cnn])?;
+    let (detections, _) =
+        detect_and_save(input_photo_path, Path::new(output_photo_path), &detectors)?;
+    Ok(detections)
+}
 
-    for r in face_locations.iter() {
-        draw_rectangle(&mut image, &r, green);
+// Outcome of one image in a batch run
+#[derive(Debug, Clone)]
+pub struct BatchResult {
+    pub image_path: String,
+    pub hog_faces: usize,
+    pub cnn_faces: usize,
+    pub width: u32,
+    pub height: u32,
+    pub detections: Vec<Detection>,
+    // Set when the image couldn't be read or the annotated copy couldn't be saved
+    pub error: Option<String>,
+}
 
-        let landmarks = tick("LandmarkPredictor", || {
-            landmarks.face_landmarks(&matrix, &r)
-        });
+/// Detects faces in every image under `input_dir` and saves annotated copies
+/// under `output_dir` at the same relative path. A failing image is recorded
+/// in its result and the batch carries on.
+pub fn detect_directory(
+    input_dir: &str,
+    output_dir: &str,
+    detectors: &Detectors,
+) -> Vec<BatchResult> {
+    list_image_files(input_dir)
+        .into_iter()
+        .map(|image_path| {
+            let relative = Path::new(&image_path)
+                .strip_prefix(input_dir)
+                .unwrap_or(Path::new(&image_path));
+            let output_path = Path::new(output_dir).join(relative);
 
-        for point in landmarks.iter() {
-            draw_point(&mut image, &point, green);
-        }
+            match detect_and_save(&image_path, &output_path, detectors) {
+                Ok((detections, (width, height))) => BatchResult {
+                    hog_faces: count_source(&detections, DetectorSource::Hog),
+                    cnn_faces: count_source(&detections, DetectorSource::Cnn),
+                    width,
+                    height,
+                    detections,
+                    image_path,
+                    error: None,
+                },
+                Err(e) => BatchResult {
+                    image_path,
+                    hog_faces: 0,
+                    cnn_faces: 0,
+                    width: 0,
+                    height: 0,
+                    detections: Vec::new(),
+                    error: Some(e.to_string()),
+                },
+            }
+        })
+        .collect()
+}
+
+pub fn write_batch_summary(results: &[BatchResult], csv_path: &str) -> Result<(), AppError> {
+    let mut file = fs::File::create(csv_path)?;
+    writeln!(file, "imagePath,hogFaces,cnnFaces,error")?;
+
+    for result in results {
+        writeln!(
+            file,
+            "{},{},{},{}",
+            csv_field(&result.image_path),
+            result.hog_faces,
+            result.cnn_faces,
+            csv_field(result.error.as_deref().unwrap_or_default())
+        )?;
     }
+    Ok(())
+}
 
-    if let Err(e) = image.save(output_photo_path) {
-        println!("Error saving the image: {e}");
-    } else {
-        println!("Output image saved to {}", output_photo_path);
+fn detect_and_save(
+    image_path: &str,
+    output_path: &Path,
+    detectors: &Detectors,
+) -> Result<(Vec<Detection>, (u32, u32)), AppError> {
+    let mut image = ImageInput::Path(image_path).load()?.into_owned();
+    let detections = detectors.detect(&image);
+
+    let thickness = stroke_scale(&image);
+    annotate(&mut image, &detections, thickness);
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)?;
     }
+    image.save(output_path)?;
+    Ok((detections, image.dimensions()))
+}
+
+fn count_source(detections: &[Detection], source: DetectorSource) -> usize {
+    detections.iter().filter(|d| d.source == source).count()
 }
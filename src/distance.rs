@@ -0,0 +1,106 @@
+use crate::compare::{euclidean_distance, FeatureSet, Gallery, Reference};
+use crate::tool::csv_field;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Symmetric matrix of pairwise Euclidean distances, stored row-major.
+pub struct DistanceMatrix {
+    pub labels: Vec<String>,
+    pub values: Vec<f64>,
+}
+
+impl DistanceMatrix {
+    pub fn from_vectors(labels: Vec<String>, vectors: &[Vec<f64>]) -> Self {
+        let n = vectors.len();
+        let mut values = vec![0.0; n * n];
+
+        // Only the upper triangle is computed, then mirrored
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let distance = euclidean_distance(&vectors[i], &vectors[j]);
+                values[i * n + j] = distance;
+                values[j * n + i] = distance;
+            }
+        }
+
+        DistanceMatrix { labels, values }
+    }
+    // Every atomic of one child against every other, labelled by photo file name
+    pub fn for_child(feature_set: &FeatureSet) -> Self {
+        let labels = feature_set
+            .atomics
+            .iter()
+            .map(|a| a.photo_file_name.clone())
+            .collect();
+        let vectors: Vec<Vec<f64>> = feature_set
+            .atomics
+            .iter()
+            .map(|a| a.feature_vector.clone())
+            .collect();
+        Self::from_vectors(labels, &vectors)
+    }
+    // Every child against every other using one aggregate, labelled by child ID
+    pub fn for_gallery(gallery: &Gallery, reference: Reference) -> Self {
+        let labels = gallery
+            .feature_sets
+            .iter()
+            .map(|fs| fs.average.child_id.clone())
+            .collect();
+        let vectors: Vec<Vec<f64>> = gallery
+            .feature_sets
+            .iter()
+            .map(|fs| fs.reference(reference).feature_vector.clone())
+            .collect();
+        Self::from_vectors(labels, &vectors)
+    }
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+    pub fn get(&self, i: usize, j: usize) -> f64 {
+        self.values[i * self.len() + j]
+    }
+    pub fn write_csv(&self, csv_path: &str) -> Result<(), String> {
+        let mut file = fs::File::create(csv_path).map_err(|e| e.to_string())?;
+
+        let header: Vec<String> = self.labels.iter().map(|l| csv_field(l)).collect();
+        writeln!(file, ",{}", header.join(",")).map_err(|e| e.to_string())?;
+
+        for (i, label) in self.labels.iter().enumerate() {
+            let row: Vec<String> = (0..self.len())
+                .map(|j| format!("{:.6}", self.get(i, j)))
+                .collect();
+            writeln!(file, "{},{}", csv_field(label), row.join(",")).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+    /// Writes the matrix as a float64 NPY array. NPY has no room for labels, so
+    /// they go to a sibling `.labels.txt` file, one per line in matrix order.
+    pub fn write_npy(&self, npy_path: &str) -> Result<(), String> {
+        let n = self.len();
+        let mut header = format!(
+            "{{'descr': '<f8', 'fortran_order': False, 'shape': ({}, {}), }}",
+            n, n
+        );
+        // Magic (6) + version (2) + header length (2) + header must align to 64 bytes
+        let unpadded = 10 + header.len() + 1;
+        header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+        header.push('\n');
+
+        let mut bytes: Vec<u8> = Vec::with_capacity(10 + header.len() + n * n * 8);
+        bytes.extend_from_slice(b"\x93NUMPY");
+        bytes.extend_from_slice(&[1, 0]);
+        bytes.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(header.as_bytes());
+        for value in &self.values {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        fs::write(npy_path, bytes).map_err(|e| e.to_string())?;
+
+        let labels_path = Path::new(npy_path).with_extension("labels.txt");
+        fs::write(labels_path, self.labels.join("\n") + "\n").map_err(|e| e.to_string())
+    }
+}
@@ -10,6 +10,39 @@ pub enum AppError {
     Sqlite(rusqlite::Error),
     Bincode(bincode::Error),
     Io(io::Error),
+    Stats(StatsError),
+    Image(image::ImageError),
+    // A dlib model file failed to load
+    Model(String),
+    Json(serde_json::Error),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatsError {
+    Empty,
+    DimensionMismatch { expected: usize, found: usize },
+    NonFinite,
+    InvalidWeights,
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StatsError::Empty => write!(f, "no feature vectors given"),
+            StatsError::DimensionMismatch { expected, found } => write!(
+                f,
+                "feature vector has {} dimensions, expected {}",
+                found, expected
+            ),
+            StatsError::NonFinite => write!(f, "feature vector contains NaN or infinity"),
+            StatsError::InvalidWeights => {
+                write!(
+                    f,
+                    "weights must match the features and sum to a positive number"
+                )
+            }
+        }
+    }
 }
 
 impl fmt::Display for AppError {
@@ -18,6 +51,10 @@ impl fmt::Display for AppError {
             AppError::Sqlite(ref err) => write!(f, "SQLite Error: {}", err),
             AppError::Bincode(ref err) => write!(f, "Bincode Error: {}", err),
             AppError::Io(ref err) => write!(f, "IO Error: {}", err),
+            AppError::Stats(ref err) => write!(f, "Stats Error: {}", err),
+            AppError::Image(ref err) => write!(f, "Image Error: {}", err),
+            AppError::Model(ref err) => write!(f, "Model Error: {}", err),
+            AppError::Json(ref err) => write!(f, "JSON Error: {}", err),
         }
     }
 }
@@ -39,3 +76,21 @@ impl From<io::Error> for AppError {
         AppError::Io(err)
     }
 }
+
+impl From<StatsError> for AppError {
+    fn from(err: StatsError) -> AppError {
+        AppError::Stats(err)
+    }
+}
+
+impl From<image::ImageError> for AppError {
+    fn from(err: image::ImageError) -> AppError {
+        AppError::Image(err)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(err: serde_json::Error) -> AppError {
+        AppError::Json(err)
+    }
+}
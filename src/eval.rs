@@ -0,0 +1,306 @@
+use crate::compare::{euclidean_distance, Gallery, Reference};
+use crate::stats::{
+    compute_average, compute_median, compute_medoid, compute_robust_average, RobustConfig,
+};
+use std::fs;
+use std::io::Write;
+
+#[derive(Debug, Clone)]
+pub struct ThresholdPoint {
+    pub threshold: f64,
+    // Share of impostor pairs accepted (distance <= threshold)
+    pub far: f64,
+    // Share of genuine pairs rejected (distance > threshold)
+    pub frr: f64,
+}
+
+impl ThresholdPoint {
+    pub fn tar(&self) -> f64 {
+        1.0 - self.frr
+    }
+}
+
+#[derive(Debug)]
+pub struct Evaluation {
+    pub reference: Reference,
+    pub num_genuine: usize,
+    pub num_impostor: usize,
+    // One point per swept threshold; (far, tar) pairs form the ROC curve
+    pub points: Vec<ThresholdPoint>,
+    pub eer: f64,
+    pub eer_threshold: f64,
+}
+
+impl Evaluation {
+    /// Highest TAR reachable while keeping FAR at or below `target_far`.
+    pub fn tar_at_far(&self, target_far: f64) -> f64 {
+        self.points
+            .iter()
+            .filter(|p| p.far <= target_far)
+            .map(|p| p.tar())
+            .fold(0.0, f64::max)
+    }
+    pub fn point_at(&self, threshold: f64) -> Option<&ThresholdPoint> {
+        self.points.iter().min_by(|a, b| {
+            (a.threshold - threshold)
+                .abs()
+                .total_cmp(&(b.threshold - threshold).abs())
+        })
+    }
+}
+
+/// Distances of each atomic to its own child's reference (genuine) and to every
+/// other child's reference (impostor). Every reference is rebuilt from the
+/// atomics in the same way, rather than read from possibly stale stored rows.
+/// Genuine references leave out the atomic being scored, so a photo is never
+/// compared with itself; children with a single atomic contribute impostor
+/// distances only.
+pub fn collect_pair_distances(
+    gallery: &Gallery,
+    reference: Reference,
+) -> Result<(Vec<f64>, Vec<f64>), String> {
+    let mut genuine = Vec::new();
+    let mut impostor = Vec::new();
+
+    let children: Vec<(Vec<Vec<f64>>, Vec<f64>)> = gallery
+        .feature_sets
+        .iter()
+        .filter(|fs| !fs.atomics.is_empty())
+        .map(|fs| {
+            let vectors: Vec<Vec<f64>> = fs
+                .atomics
+                .iter()
+                .map(|a| a.feature_vector.clone())
+                .collect();
+            let full = build_reference(&vectors, reference)?;
+            Ok((vectors, full))
+        })
+        .collect::<Result<_, String>>()?;
+
+    for (c, (vectors, _)) in children.iter().enumerate() {
+        // Leave-one-out Average is derived from the plain average in closed form
+        let average = compute_average(vectors).map_err(|e| e.to_string())?;
+
+        for (i, vector) in vectors.iter().enumerate() {
+            if vectors.len() > 1 {
+                let own = leave_one_out(vectors, &average, i, reference)?;
+                genuine.push(euclidean_distance(vector, &own));
+            }
+            for (o, (_, other_reference)) in children.iter().enumerate() {
+                if o != c {
+                    impostor.push(euclidean_distance(vector, other_reference));
+                }
+            }
+        }
+    }
+
+    Ok((genuine, impostor))
+}
+
+// The reference built from `vectors`, the same way for genuine and impostor pairs
+fn build_reference(vectors: &[Vec<f64>], reference: Reference) -> Result<Vec<f64>, String> {
+    match reference {
+        Reference::Average => compute_average(vectors),
+        Reference::Median => compute_median(vectors),
+        Reference::Medoid => compute_medoid(vectors).map(|i| vectors[i].clone()),
+        Reference::Robust => compute_robust_average(vectors, &RobustConfig::default()),
+    }
+    .map_err(|e| e.to_string())
+}
+
+// The child's reference computed from every atomic except `excluded`
+fn leave_one_out(
+    vectors: &[Vec<f64>],
+    average: &[f64],
+    excluded: usize,
+    reference: Reference,
+) -> Result<Vec<f64>, String> {
+    if let Reference::Average = reference {
+        let n = vectors.len() as f64;
+        return Ok(average
+            .iter()
+            .zip(vectors[excluded].iter())
+            .map(|(avg, x)| (n * avg - x) / (n - 1.0))
+            .collect());
+    }
+
+    let rest: Vec<Vec<f64>> = vectors
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != excluded)
+        .map(|(_, v)| v.clone())
+        .collect();
+    build_reference(&rest, reference)
+}
+
+/// Sweeps thresholds from `start` to `end` (inclusive) in `step` increments.
+pub fn evaluate(
+    gallery: &Gallery,
+    reference: Reference,
+    start: f64,
+    end: f64,
+    step: f64,
+) -> Result<Evaluation, String> {
+    if !(step > 0.0 && end >= start) {
+        return Err(format!(
+            "Invalid threshold sweep: start {}, end {}, step {}",
+            start, end, step
+        ));
+    }
+    let (mut genuine, mut impostor) = collect_pair_distances(gallery, reference)?;
+    genuine.sort_by(|a, b| a.total_cmp(b));
+    impostor.sort_by(|a, b| a.total_cmp(b));
+
+    let num_steps = ((end - start) / step).round() as usize;
+    let points: Vec<ThresholdPoint> = (0..=num_steps)
+        .map(|i| {
+            let threshold = start + i as f64 * step;
+            let accepted_impostors = impostor.partition_point(|&d| d <= threshold);
+            let accepted_genuines = genuine.partition_point(|&d| d <= threshold);
+            ThresholdPoint {
+                threshold,
+                far: ratio(accepted_impostors, impostor.len()),
+                frr: 1.0 - ratio(accepted_genuines, genuine.len()),
+            }
+        })
+        .collect();
+
+    let (eer, eer_threshold) = points
+        .iter()
+        .min_by(|a, b| (a.far - a.frr).abs().total_cmp(&(b.far - b.frr).abs()))
+        .map(|p| ((p.far + p.frr) / 2.0, p.threshold))
+        .unwrap_or((0.0, 0.0));
+
+    Ok(Evaluation {
+        reference,
+        num_genuine: genuine.len(),
+        num_impostor: impostor.len(),
+        points,
+        eer,
+        eer_threshold,
+    })
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+pub fn write_evaluation_csv(evaluations: &[Evaluation], csv_path: &str) -> Result<(), String> {
+    let mut file = fs::File::create(csv_path).map_err(|e| e.to_string())?;
+    writeln!(file, "reference,threshold,far,frr,tar").map_err(|e| e.to_string())?;
+
+    for evaluation in evaluations {
+        for p in &evaluation.points {
+            writeln!(
+                file,
+                "{:?},{:.4},{:.6},{:.6},{:.6}",
+                evaluation.reference,
+                p.threshold,
+                p.far,
+                p.frr,
+                p.tar()
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+pub fn format_summary_table(evaluations: &[Evaluation], threshold: f64) -> String {
+    let mut table = format!(
+        "{:<10}{:>10}{:>12}{:>10}{:>12}{:>14}{:>15}{:>10}{:>10}\n",
+        "Reference",
+        "Genuine",
+        "Impostor",
+        "EER",
+        "EER thr.",
+        "TAR@FAR=1%",
+        "TAR@FAR=0.1%",
+        "FAR@thr",
+        "FRR@thr"
+    );
+    for evaluation in evaluations {
+        let (far, frr) = evaluation
+            .point_at(threshold)
+            .map(|p| (p.far, p.frr))
+            .unwrap_or((0.0, 0.0));
+        table.push_str(&format!(
+            "{:<10}{:>10}{:>12}{:>10.4}{:>12.2}{:>14.4}{:>15.4}{:>10.4}{:>10.4}\n",
+            format!("{:?}", evaluation.reference),
+            evaluation.num_genuine,
+            evaluation.num_impostor,
+            evaluation.eer,
+            evaluation.eer_threshold,
+            evaluation.tar_at_far(0.01),
+            evaluation.tar_at_far(0.001),
+            far,
+            frr
+        ));
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compare::{FaceEncoding, FeatureSet};
+
+    // Two children whose photos sit within 0.05 of (0, 0) and (1, 0) respectively
+    fn two_child_gallery() -> Gallery {
+        let child = |id: &str, x: f64| {
+            let atomics = (0..5)
+                .map(|i| {
+                    let offset = 0.01 * i as f64;
+                    FaceEncoding::aggregate(
+                        id,
+                        &format!("{}_{}.jpg", id, i),
+                        "Atomic",
+                        vec![x + offset, offset],
+                    )
+                })
+                .collect();
+            FeatureSet::from_atomics(id, atomics).unwrap()
+        };
+        Gallery {
+            feature_sets: vec![child("A", 0.0), child("B", 1.0)],
+            skipped: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn separated_children_have_zero_eer() {
+        let gallery = two_child_gallery();
+        for reference in [
+            Reference::Average,
+            Reference::Median,
+            Reference::Medoid,
+            Reference::Robust,
+        ] {
+            let evaluation = evaluate(&gallery, reference, 0.0, 1.0, 0.01).unwrap();
+            assert_eq!(evaluation.num_genuine, 10);
+            assert_eq!(evaluation.num_impostor, 10);
+            assert_eq!(evaluation.eer, 0.0);
+            assert!(evaluation.eer_threshold > 0.0 && evaluation.eer_threshold < 0.9);
+            assert_eq!(evaluation.tar_at_far(0.0), 1.0);
+        }
+    }
+
+    #[test]
+    fn genuine_distances_leave_the_photo_out() {
+        let gallery = two_child_gallery();
+        let (genuine, _) = collect_pair_distances(&gallery, Reference::Medoid).unwrap();
+        assert!(genuine.iter().all(|&d| d > 0.0));
+    }
+
+    #[test]
+    fn invalid_sweep_is_rejected() {
+        let gallery = two_child_gallery();
+        assert!(evaluate(&gallery, Reference::Average, 0.0, 1.0, 0.0).is_err());
+        assert!(evaluate(&gallery, Reference::Average, 1.0, 0.0, 0.01).is_err());
+        assert!(evaluate(&gallery, Reference::Average, 0.0, 1.0, f64::NAN).is_err());
+    }
+}
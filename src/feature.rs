@@ -1,8 +1,23 @@
-use crate::dbs::{create_face_encodings_table, insert_face_encoding};
-use crate::stats::{compute_average, compute_median};
-use crate::tool::{get_full_file_name, tick};
+use crate::archive::{for_each_entry, is_archive, ArchiveEntry};
+use crate::compare::FaceEncoding;
+use crate::dbs::{
+    create_child_aggregates_table, create_face_encodings_table, get_atomic_ids_by_photo,
+    get_face_encoding_by_id, get_features_by_child_id, insert_face_encoding, replace_aggregates,
+    save_online_stats, update_atomics,
+};
+use crate::error::StatsError;
+use crate::photos::{
+    photo_child_id, read_capture_date, read_capture_date_from_bytes, CAPTURE_DATE_FORMAT,
+};
+use crate::stats::{
+    compute_average, compute_median, compute_medoid, compute_prototypes, compute_robust_average,
+    OnlineStats, RobustConfig,
+};
+use crate::tool::get_full_file_name;
 use image::ImageError;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
+use tracing::{info, info_span, warn};
 use walkdir::WalkDir;
 
 use dlib_face_recognition::*;
@@ -12,6 +27,9 @@ pub enum FeatureType {
     Atomic,
     Average,
     Median,
+    Medoid,
+    Robust,
+    Prototype,
 }
 
 #[derive(Debug)]
@@ -20,6 +38,11 @@ pub struct Feature {
     feature_vector: Vec<f64>,
     photo_file_name: String,
     f_type: FeatureType,
+    capture_date: Option<String>,
+    // Archive the photo was read from, if any
+    origin: Option<String>,
+    // Path of the photo file, or its entry name inside the archive
+    photo_path: Option<String>,
 }
 impl Feature {
     pub fn from_image(
@@ -29,29 +52,57 @@ impl Feature {
         landmark_predictor: &LandmarkPredictor,
         face_encoder: &FaceEncoderNetwork,
     ) -> Result<Self, String> {
-        let image_buffer = image::open(photo_path)
+        let image_buffer = info_span!("load_image")
+            .in_scope(|| image::open(photo_path))
             .map_err(|e| format!("Error opening image {}: {}", photo_path, e))?
             .to_rgb8();
-        let image_matrix = ImageMatrix::from_image(&image_buffer);
-
-        let face_locations = face_detector.face_locations(&image_matrix);
-        let face_location = face_locations
-            .first()
-            .ok_or_else(|| "No faces detected in the image".to_string())?;
-
-        let landmarks = landmark_predictor.face_landmarks(&image_matrix, face_location);
-        let encodings = face_encoder.get_face_encodings(&image_matrix, &[landmarks], 0);
-        let feature_vector = encodings
-            .first()
-            .ok_or_else(|| "Unable to encode face features".to_string())?
-            .as_ref()
-            .to_owned();
+        let feature_vector = encode_first_face(
+            &image_buffer,
+            face_detector,
+            landmark_predictor,
+            face_encoder,
+        )?;
 
         Ok(Feature {
             child_id: child_id.to_owned(),
             feature_vector,
             photo_file_name: get_full_file_name(photo_path).to_owned(),
             f_type: FeatureType::Atomic,
+            capture_date: read_capture_date(photo_path)
+                .map(|date| date.format(CAPTURE_DATE_FORMAT).to_string()),
+            origin: None,
+            photo_path: Some(photo_path.to_owned()),
+        })
+    }
+    // A photo read from a .zip or .tar(.gz) archive; the archive path is kept as its origin
+    pub fn from_archive_entry(
+        child_id: &str,
+        archive_path: &str,
+        entry: &ArchiveEntry,
+        face_detector: &FaceDetectorCnn,
+        landmark_predictor: &LandmarkPredictor,
+        face_encoder: &FaceEncoderNetwork,
+    ) -> Result<Self, String> {
+        let image_buffer = info_span!("load_image")
+            .in_scope(|| image::load_from_memory(&entry.bytes))
+            .map_err(|e| format!("Error decoding {} in {}: {}", entry.name, archive_path, e))?
+            .to_rgb8();
+        let feature_vector = encode_first_face(
+            &image_buffer,
+            face_detector,
+            landmark_predictor,
+            face_encoder,
+        )?;
+
+        Ok(Feature {
+            child_id: child_id.to_owned(),
+            feature_vector,
+            photo_file_name: get_full_file_name(&entry.name).to_owned(),
+            f_type: FeatureType::Atomic,
+            capture_date: read_capture_date_from_bytes(&entry.bytes, entry.modified)
+                .map(|date| date.format(CAPTURE_DATE_FORMAT).to_string()),
+            origin: Some(archive_path.to_owned()),
+            photo_path: Some(entry.name.clone()),
         })
     }
     pub fn from_vector(
@@ -65,20 +116,69 @@ impl Feature {
             feature_vector,
             photo_file_name: get_full_file_name(photo_path).to_owned(),
             f_type,
+            capture_date: None,
+            origin: None,
+            photo_path: None,
+        }
+    }
+    // The unsaved row this feature would be stored as
+    fn to_encoding(&self) -> FaceEncoding {
+        FaceEncoding {
+            capture_date: self.capture_date.clone(),
+            origin: self.origin.clone(),
+            photo_path: self.photo_path.clone(),
+            ..FaceEncoding::aggregate(
+                &self.child_id,
+                &self.photo_file_name,
+                &format!("{:?}", self.f_type),
+                self.feature_vector.clone(),
+            )
         }
     }
     pub fn save(&self, db_path: &str) -> Result<(), String> {
-        insert_face_encoding(
-            db_path,
-            &self.child_id,
-            &self.feature_vector,
-            &self.photo_file_name,
-            &format!("{:?}", &self.f_type),
-        )
-        .map_err(|e| e.to_string())
+        let _span = info_span!("db_write").entered();
+        insert_face_encoding(db_path, &self.to_encoding()).map_err(|e| e.to_string())
     }
 }
 
+// Encoding of the first face found, shared by every photo source
+fn encode_first_face(
+    image_buffer: &image::RgbImage,
+    face_detector: &FaceDetectorCnn,
+    landmark_predictor: &LandmarkPredictor,
+    face_encoder: &FaceEncoderNetwork,
+) -> Result<Vec<f64>, String> {
+    let image_matrix = ImageMatrix::from_image(image_buffer);
+
+    let face_locations =
+        info_span!("detect").in_scope(|| face_detector.face_locations(&image_matrix));
+    let face_location = face_locations
+        .first()
+        .ok_or_else(|| "No faces detected in the image".to_string())?;
+
+    let landmarks = info_span!("landmark")
+        .in_scope(|| landmark_predictor.face_landmarks(&image_matrix, face_location));
+    let encodings = info_span!("encode")
+        .in_scope(|| face_encoder.get_face_encodings(&image_matrix, &[landmarks], 0));
+    Ok(encodings
+        .first()
+        .ok_or_else(|| "Unable to encode face features".to_string())?
+        .as_ref()
+        .to_owned())
+}
+
+// Each photo's path or archive entry name with the outcome of encoding it
+type EncodedPhotos = Vec<(String, Result<Feature, String>)>;
+
+// Which optional aggregates are stored next to Average, Median and Medoid
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AggregateConfig {
+    // Store a Robust (outlier-excluding) centroid
+    pub robust: Option<RobustConfig>,
+    // Number of Prototype rows per child; 0 stores none
+    pub prototype_count: usize,
+}
+
 pub struct Features {
     features: Vec<Feature>,
     photos_dir_path: String,
@@ -86,6 +186,7 @@ pub struct Features {
     face_detector: FaceDetectorCnn,
     landmark_predictor: LandmarkPredictor,
     face_encoder: FaceEncoderNetwork,
+    aggregate_config: AggregateConfig,
 }
 
 impl Features {
@@ -101,34 +202,129 @@ impl Features {
             face_detector: FaceDetectorCnn::default()?,
             landmark_predictor: LandmarkPredictor::default()?,
             face_encoder: FaceEncoderNetwork::default()?,
+            aggregate_config: AggregateConfig::default(),
         })
     }
+    // Also store a Robust aggregate (outlier-excluding centroid) for each child
+    pub fn set_robust_config(&mut self, robust_config: Option<RobustConfig>) {
+        self.aggregate_config.robust = robust_config;
+    }
+    // Also store this many k-means prototypes for each child
+    pub fn set_prototype_count(&mut self, prototype_count: usize) {
+        self.aggregate_config.prototype_count = prototype_count;
+    }
     fn init_db(db_dir_path: &str) -> Result<(), String> {
-        create_face_encodings_table(db_dir_path).map_err(|e| e.to_string())
+        create_face_encodings_table(db_dir_path).map_err(|e| e.to_string())?;
+        create_child_aggregates_table(db_dir_path).map_err(|e| e.to_string())
+    }
+    // Extract and save one photo, replacing the atomics earlier extracted from the
+    // same path, and update the child's aggregates incrementally in the same
+    // transaction. On error the stored rows are left as they were.
+    pub fn enroll_photo(&mut self, child_id: &str, photo_path: &str) -> Result<(), String> {
+        let feature = Feature::from_image(
+            child_id,
+            photo_path,
+            &self.face_detector,
+            &self.landmark_predictor,
+            &self.face_encoder,
+        )?;
+        let previous_ids = get_atomic_ids_by_photo(
+            &self.db_dir_path,
+            child_id,
+            photo_path,
+            &feature.photo_file_name,
+        )
+        .map_err(|e| e.to_string())?;
+
+        update_atomics(
+            &self.db_dir_path,
+            child_id,
+            &[feature.to_encoding()],
+            &previous_ids,
+        )
+        .map_err(|e| format!("Child ID {}: {}", child_id, e))?;
+        info!(child_id, photo = %photo_path, replaced = previous_ids.len(), "Enrolled photo");
+        Ok(())
     }
     pub fn process_photos(&mut self, child_id: &str) -> Result<(), String> {
-        const BATCH_SIZE: usize = 1000;
-        let mut individual_feature_vectors: Vec<Vec<f64>> = Vec::new();
+        if is_archive(&self.photos_dir_path) {
+            let child_ids = HashSet::from([child_id.to_owned()]);
+            return self
+                .process_archive(&child_ids)?
+                .remove(child_id)
+                .unwrap_or(Ok(()));
+        }
+        let _span = info_span!("process_photos", child_id).entered();
 
-        // Collect eligible photo paths
         let photo_paths: Vec<_> = WalkDir::new(&self.photos_dir_path)
             .into_iter()
             .filter_map(|e| e.ok())
             .filter(|e| e.path().is_file() && is_target_file(e.path(), child_id))
             .filter_map(|e| e.path().to_str().map(String::from))
             .collect();
+        let results = photo_paths
+            .into_iter()
+            .map(|photo_path| {
+                let feature = Feature::from_image(
+                    child_id,
+                    &photo_path,
+                    &self.face_detector,
+                    &self.landmark_predictor,
+                    &self.face_encoder,
+                );
+                (photo_path, feature)
+            })
+            .collect();
+        self.store_photos(child_id, results)
+    }
+    /// Processes every child in `child_ids` with a single pass over the archive
+    /// at the photos path, returning each child's outcome. A .tar.gz can only be
+    /// decompressed front to back, so reading it once per child would not scale.
+    pub fn process_archive(
+        &mut self,
+        child_ids: &HashSet<String>,
+    ) -> Result<HashMap<String, Result<(), String>>, String> {
+        let mut results: HashMap<String, EncodedPhotos> = HashMap::new();
+        for_each_entry(
+            &self.photos_dir_path,
+            |name| archive_child_id(name, child_ids).is_some(),
+            |entry| {
+                let Some(child_id) = archive_child_id(&entry.name, child_ids) else {
+                    return;
+                };
+                let feature = Feature::from_archive_entry(
+                    &child_id,
+                    &self.photos_dir_path,
+                    &entry,
+                    &self.face_detector,
+                    &self.landmark_predictor,
+                    &self.face_encoder,
+                );
+                results
+                    .entry(child_id)
+                    .or_default()
+                    .push((entry.name, feature));
+            },
+        )?;
 
-        // Sequential processing for each photo path
-        for photo_path in photo_paths {
-            match Feature::from_image(
-                child_id,
-                &photo_path,
-                &self.face_detector,
-                &self.landmark_predictor,
-                &self.face_encoder,
-            ) {
+        Ok(child_ids
+            .iter()
+            .map(|child_id| {
+                let _span = info_span!("process_photos", child_id = %child_id).entered();
+                let photos = results.remove(child_id).unwrap_or_default();
+                (child_id.clone(), self.store_photos(child_id, photos))
+            })
+            .collect())
+    }
+    // Saves a child's newly encoded photos and rebuilds its aggregates
+    fn store_photos(&mut self, child_id: &str, results: EncodedPhotos) -> Result<(), String> {
+        const BATCH_SIZE: usize = 1000;
+        let mut extracted = 0;
+
+        for (photo_path, result) in results {
+            match result {
                 Ok(feature) => {
-                    individual_feature_vectors.push(feature.feature_vector.clone());
+                    extracted += 1;
                     self.features.push(feature);
 
                     if self.features.len() >= BATCH_SIZE {
@@ -137,29 +333,23 @@ impl Features {
                         self.features.clear(); // Clear features after saving
                     }
                 }
-                Err(e) => eprintln!("Error processing image {}: {}", photo_path, e),
+                Err(e) => warn!(photo = %photo_path, error = %e, "Skipping image"),
             }
         }
-        // Calculate average and median feature vectors
-        if !individual_feature_vectors.is_empty() {
-            let average_vector = compute_average(&individual_feature_vectors);
-            let median_vector = compute_median(&individual_feature_vectors);
-
-            let average_feature =
-                Feature::from_vector(child_id, "average", average_vector, FeatureType::Average);
-            let median_feature =
-                Feature::from_vector(child_id, "median", median_vector, FeatureType::Median);
-
-            self.features.push(average_feature);
-            self.features.push(median_feature);
-        }
+        info!(photos = extracted, "Extracted atomics");
 
-        // Save any remaining features (including average and median features)
+        // Save any remaining atomics
         if !self.features.is_empty() {
             self.save_features_batch(&self.features)?;
             self.features.clear();
         }
-        Ok(())
+        if extracted == 0 {
+            return Ok(());
+        }
+
+        // Rebuild the aggregates from every stored atomic, including those from
+        // earlier runs, replacing the child's previous aggregate rows
+        recompute_aggregates(&self.db_dir_path, child_id, &self.aggregate_config)
     }
     // Function to save a batch of features
     pub fn save_features_batch(&self, features: &[Feature]) -> Result<(), String> {
@@ -176,17 +366,152 @@ impl Features {
 
         Ok(())
     }
+    // Encode every face found in a photo, in detection order
+    pub fn encode_faces(&self, photo_path: &str) -> Result<Vec<Vec<f64>>, String> {
+        let image_buffer = image::open(photo_path)
+            .map_err(|e| format!("Error opening image {}: {}", photo_path, e))?
+            .to_rgb8();
+        let image_matrix = ImageMatrix::from_image(&image_buffer);
+
+        Ok(encode_all_faces(
+            &image_matrix,
+            &self.face_detector,
+            &self.landmark_predictor,
+            &self.face_encoder,
+        )
+        .into_iter()
+        .map(|(_, feature_vector)| feature_vector)
+        .collect())
+    }
     pub fn get_features(&self) -> &Vec<Feature> {
         return &self.features;
     }
 }
+// Detect every face in the image and encode it, keeping the face rectangle
+pub fn encode_all_faces(
+    image_matrix: &ImageMatrix,
+    face_detector: &FaceDetectorCnn,
+    landmark_predictor: &LandmarkPredictor,
+    face_encoder: &FaceEncoderNetwork,
+) -> Vec<(Rectangle, Vec<f64>)> {
+    let face_locations =
+        info_span!("detect").in_scope(|| face_detector.face_locations(image_matrix));
+    let landmarks: Vec<FaceLandmarks> = info_span!("landmark").in_scope(|| {
+        face_locations
+            .iter()
+            .map(|r| landmark_predictor.face_landmarks(image_matrix, r))
+            .collect()
+    });
+    let encodings = info_span!("encode")
+        .in_scope(|| face_encoder.get_face_encodings(image_matrix, &landmarks, 0));
+
+    face_locations
+        .iter()
+        .zip(encodings.iter())
+        .map(|(r, e)| (*r, e.as_ref().to_owned()))
+        .collect()
+}
+fn aggregate_features(
+    child_id: &str,
+    feature_vectors: &[Vec<f64>],
+    photo_file_names: &[String],
+    config: &AggregateConfig,
+) -> Result<Vec<Feature>, String> {
+    let to_string = |e: StatsError| format!("Child ID {}: {}", child_id, e);
+    let average_vector = compute_average(feature_vectors).map_err(to_string)?;
+    let median_vector = compute_median(feature_vectors).map_err(to_string)?;
+    // The medoid is a real photo, so it keeps that photo's file name
+    let medoid_index = compute_medoid(feature_vectors).map_err(to_string)?;
+
+    let mut aggregates = vec![
+        Feature::from_vector(child_id, "average", average_vector, FeatureType::Average),
+        Feature::from_vector(child_id, "median", median_vector, FeatureType::Median),
+        Feature::from_vector(
+            child_id,
+            &photo_file_names[medoid_index],
+            feature_vectors[medoid_index].clone(),
+            FeatureType::Medoid,
+        ),
+    ];
+    if let Some(robust_config) = &config.robust {
+        let robust_vector =
+            compute_robust_average(feature_vectors, robust_config).map_err(to_string)?;
+        aggregates.push(Feature::from_vector(
+            child_id,
+            "robust",
+            robust_vector,
+            FeatureType::Robust,
+        ));
+    }
+    let prototypes =
+        compute_prototypes(feature_vectors, config.prototype_count, 100).map_err(to_string)?;
+    for (i, prototype) in prototypes.into_iter().enumerate() {
+        aggregates.push(Feature::from_vector(
+            child_id,
+            &format!("prototype_{}", i),
+            prototype,
+            FeatureType::Prototype,
+        ));
+    }
+    Ok(aggregates)
+}
+// Rewrite the stored aggregate rows of a child from its atomics
+pub fn recompute_aggregates(
+    db_path: &str,
+    child_id: &str,
+    config: &AggregateConfig,
+) -> Result<(), String> {
+    let feature_set = get_features_by_child_id(db_path, child_id).map_err(|e| e.to_string())?;
+    let feature_vectors: Vec<Vec<f64>> = feature_set
+        .atomics
+        .iter()
+        .map(|atomic| atomic.feature_vector.clone())
+        .collect();
+    let photo_file_names: Vec<String> = feature_set
+        .atomics
+        .iter()
+        .map(|atomic| atomic.photo_file_name.clone())
+        .collect();
+    let aggregates: Vec<FaceEncoding> =
+        aggregate_features(child_id, &feature_vectors, &photo_file_names, config)?
+            .iter()
+            .map(Feature::to_encoding)
+            .collect();
+
+    replace_aggregates(db_path, child_id, &aggregates).map_err(|e| e.to_string())?;
+    seed_online_stats(db_path, child_id, &feature_vectors)
+}
+fn seed_online_stats(
+    db_path: &str,
+    child_id: &str,
+    feature_vectors: &[Vec<f64>],
+) -> Result<(), String> {
+    create_child_aggregates_table(db_path).map_err(|e| e.to_string())?;
+    let online_stats = OnlineStats::from_features(feature_vectors)
+        .map_err(|e| format!("Child ID {}: {}", child_id, e))?;
+    save_online_stats(db_path, child_id, &online_stats).map_err(|e| e.to_string())
+}
+// Delete one atomic encoding and take it out of its child's aggregates
+pub fn delete_atomic(db_path: &str, id: i32) -> Result<(), String> {
+    let encoding = get_face_encoding_by_id(db_path, id)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No face encoding with ID {}", id))?;
+    if encoding.f_type != format!("{:?}", FeatureType::Atomic) {
+        return Err(format!(
+            "Face encoding {} is an aggregate, not an atomic",
+            id
+        ));
+    }
+
+    update_atomics(db_path, &encoding.child_id, &[], &[id])
+        .map_err(|e| format!("Child ID {}: {}", encoding.child_id, e))
+}
+
+// Child ID of an archive entry that is a photo of one of `child_ids`
+fn archive_child_id(entry_name: &str, child_ids: &HashSet<String>) -> Option<String> {
+    photo_child_id(Path::new(entry_name)).filter(|child_id| child_ids.contains(child_id))
+}
+
 fn is_target_file(path: &Path, child_id: &str) -> bool {
-    path.file_name()
-        .and_then(|name| name.to_str())
-        .map(|name| {
-            let lower_name = name.to_lowercase();
-            (lower_name.ends_with(".jpg") || lower_name.ends_with(".png"))
-                && lower_name.starts_with(child_id)
-        })
-        .unwrap_or(false)
+    photo_child_id(path).as_deref() == Some(child_id)
 }
@@ -0,0 +1,144 @@
+use crate::cluster::{chinese_whispers, group_by_label};
+use crate::compare::Gallery;
+use crate::feature::Features;
+use crate::photos::list_image_files;
+use crate::stats::compute_average;
+use crate::tool::{csv_field, get_full_file_name};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct IntakeFace {
+    pub photo_path: String,
+    pub face_index: usize,
+    pub feature_vector: Vec<f64>,
+}
+
+#[derive(Debug)]
+pub struct IdentityCluster {
+    pub faces: Vec<IntakeFace>,
+    // Closest enrolled child within the threshold, if any
+    pub matched_child_id: Option<String>,
+    pub distance: Option<f64>,
+}
+
+impl IdentityCluster {
+    pub fn label(&self, index: usize) -> String {
+        match &self.matched_child_id {
+            Some(child_id) => child_id.clone(),
+            None => format!("unknown_{}", index),
+        }
+    }
+}
+
+/// Encodes every face in an unlabeled folder and groups them into proposed
+/// identities, matching each group against the enrolled gallery in `db_path`.
+pub fn cluster_intake(
+    photo_dir: &str,
+    db_path: &str,
+    threshold: f64,
+) -> Result<Vec<IdentityCluster>, String> {
+    let fts = Features::new(photo_dir.to_owned(), db_path.to_owned())?;
+    let gallery = Gallery::from_db_table(db_path)?;
+
+    let mut faces: Vec<IntakeFace> = Vec::new();
+    for photo_path in list_image_files(photo_dir) {
+        match fts.encode_faces(&photo_path) {
+            Ok(encodings) => {
+                for (face_index, feature_vector) in encodings.into_iter().enumerate() {
+                    faces.push(IntakeFace {
+                        photo_path: photo_path.clone(),
+                        face_index,
+                        feature_vector,
+                    });
+                }
+            }
+            Err(e) => tracing::warn!(photo = %photo_path, error = %e, "Skipping image"),
+        }
+    }
+
+    let vectors: Vec<Vec<f64>> = faces.iter().map(|f| f.feature_vector.clone()).collect();
+    let labels = chinese_whispers(&vectors, threshold, 100);
+
+    group_by_label(&faces, &labels)
+        .into_iter()
+        .map(|faces| {
+            let vectors: Vec<Vec<f64>> = faces.iter().map(|f| f.feature_vector.clone()).collect();
+            let centroid = compute_average(&vectors).map_err(|e| e.to_string())?;
+            let matched = gallery.identify(&centroid, threshold);
+            Ok(IdentityCluster {
+                faces,
+                matched_child_id: matched.as_ref().map(|(child_id, _)| child_id.clone()),
+                distance: matched.map(|(_, distance)| distance),
+            })
+        })
+        .collect()
+}
+
+// CSV with one row per face: cluster,matchedChildID,distance,photoPath,faceIndex
+pub fn write_intake_report(clusters: &[IdentityCluster], report_path: &str) -> Result<(), String> {
+    let mut file = fs::File::create(report_path).map_err(|e| e.to_string())?;
+    writeln!(file, "cluster,matchedChildID,distance,photoPath,faceIndex")
+        .map_err(|e| e.to_string())?;
+
+    for (i, cluster) in clusters.iter().enumerate() {
+        let child_id = cluster.matched_child_id.clone().unwrap_or_default();
+        let distance = cluster
+            .distance
+            .map(|d| format!("{:.4}", d))
+            .unwrap_or_default();
+        for face in &cluster.faces {
+            writeln!(
+                file,
+                "{},{},{},{},{}",
+                i,
+                csv_field(&child_id),
+                distance,
+                csv_field(&face.photo_path),
+                face.face_index
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+// Copies each cluster's photos into `output_dir/<child ID or unknown_N>/`.
+// Same-named photos from different folders get a `_1`, `_2`, ... suffix.
+pub fn copy_into_folders(clusters: &[IdentityCluster], output_dir: &str) -> Result<(), String> {
+    for (i, cluster) in clusters.iter().enumerate() {
+        let cluster_dir = Path::new(output_dir).join(cluster.label(i));
+        fs::create_dir_all(&cluster_dir).map_err(|e| e.to_string())?;
+
+        // Target file -> source photo, so a photo with several faces is copied once
+        let mut copied: HashMap<PathBuf, &str> = HashMap::new();
+        for face in &cluster.faces {
+            let target = unique_target(&cluster_dir, &face.photo_path, &copied);
+            if copied.get(&target) == Some(&face.photo_path.as_str()) {
+                continue;
+            }
+            fs::copy(&face.photo_path, &target).map_err(|e| e.to_string())?;
+            copied.insert(target, &face.photo_path);
+        }
+    }
+    Ok(())
+}
+
+// First of `name`, `name_1`, `name_2`, ... not taken by a different photo
+fn unique_target(dir: &Path, photo_path: &str, copied: &HashMap<PathBuf, &str>) -> PathBuf {
+    let file_name = get_full_file_name(photo_path);
+    let path = Path::new(&file_name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let extension = path.extension().and_then(|e| e.to_str());
+
+    (0..)
+        .map(|n| match (n, extension) {
+            (0, _) => dir.join(&file_name),
+            (_, Some(ext)) => dir.join(format!("{}_{}.{}", stem, n, ext)),
+            (_, None) => dir.join(format!("{}_{}", stem, n)),
+        })
+        .find(|candidate| copied.get(candidate).is_none_or(|s| *s == photo_path))
+        .unwrap_or_else(|| dir.join(&file_name))
+}
@@ -1,8 +1,24 @@
+pub mod annotation;
+pub mod archive;
+pub mod benchmark;
+pub mod chip;
+pub mod cluster;
 pub mod compare;
 pub mod dbs;
 pub mod detect;
+pub mod distance;
 pub mod error;
+pub mod eval;
 pub mod feature;
+pub mod font;
+pub mod intake;
 pub mod photos;
+pub mod projection;
+pub mod report;
+pub mod review;
+pub mod sheet;
 pub mod stats;
+pub mod tag;
+pub mod telemetry;
 pub mod tool;
+pub mod watch;
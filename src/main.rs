@@ -1,56 +1,82 @@
 use std::collections::HashSet;
 
+use face_rec_dlib::annotation::*;
+use face_rec_dlib::archive::is_archive;
+use face_rec_dlib::benchmark::*;
+use face_rec_dlib::chip::*;
 use face_rec_dlib::compare::*;
 use face_rec_dlib::dbs::*;
 use face_rec_dlib::detect::*;
+use face_rec_dlib::distance::*;
+use face_rec_dlib::eval::*;
 use face_rec_dlib::feature::*;
+use face_rec_dlib::intake::*;
 use face_rec_dlib::photos::extract_unique_child_ids;
+use face_rec_dlib::projection::*;
+use face_rec_dlib::report::*;
+use face_rec_dlib::review::*;
+use face_rec_dlib::sheet::*;
+use face_rec_dlib::stats::RobustConfig;
+use face_rec_dlib::tag::*;
+use face_rec_dlib::telemetry::*;
+use face_rec_dlib::watch::watch_and_enroll;
 use progress_bar::*;
+use tracing::error;
 
 use std::io::{self, Write};
+use std::time::Duration;
 
 fn extract_photos(photo_path: &str, db_path: &str, child_ids: &HashSet<String>) {
+    stage_timings().reset();
     init_progress_bar(child_ids.len());
     set_progress_bar_action("Extracting", Color::Blue, Style::Bold);
 
     match Features::new(photo_path.to_owned(), db_path.to_owned()) {
         Ok(mut fts) => {
-            for id in child_ids {
-                if let Err(e) = fts.process_photos(&id) {
-                    print_progress_bar_info(
-                        "Failed",
-                        &format!("Error processing photos for child ID {}: {}", id, e),
-                        Color::Red,
-                        Style::Normal,
-                    );
-                    continue;
+            fts.set_robust_config(Some(RobustConfig::default()));
+            fts.set_prototype_count(DEFAULT_PROTOTYPE_COUNT);
+            if is_archive(photo_path) {
+                // One pass over the archive covers every child
+                match fts.process_archive(child_ids) {
+                    Ok(results) => {
+                        for (id, result) in results {
+                            if let Err(e) = result {
+                                error!(child_id = %id, error = %e, "Error processing photos");
+                                continue;
+                            }
+                            inc_progress_bar();
+                        }
+                    }
+                    Err(e) => error!(archive = photo_path, error = %e, "Error reading archive"),
                 }
+            } else {
+                for id in child_ids {
+                    if let Err(e) = fts.process_photos(&id) {
+                        error!(child_id = %id, error = %e, "Error processing photos");
+                        continue;
+                    }
 
-                inc_progress_bar();
+                    inc_progress_bar();
+                }
             }
             // After processing all child IDs, save any remaining features
             if !fts.get_features().is_empty() {
                 if let Err(e) = fts.save_features_batch(&fts.get_features()) {
-                    //eprintln!("Error saving final batch of features: {}", e);
-                    print_progress_bar_info(
-                        "Failed",
-                        &format!("Error saving final batch of features: {}", e),
-                        Color::Red,
-                        Style::Normal,
-                    );
+                    error!(error = %e, "Error saving final batch of features");
                 }
             }
         }
         Err(e) => {
-            print_progress_bar_info(
-                "Failed",
-                &format!("Failed to initialize ChildFeatures: {}", e),
-                Color::Red,
-                Style::Normal,
-            );
+            error!(error = %e, "Failed to initialize ChildFeatures");
         }
     }
     finalize_progress_bar();
+
+    if LogFormat::from_env() == LogFormat::Json {
+        log_stage_summary();
+    } else {
+        print!("{}", stage_timings().format_summary());
+    }
 }
 fn find_distants_feature(db_path: &str, child_ids: &HashSet<String>, treshold: f64) {
     // init_progress_bar(child_ids.len());
@@ -58,12 +84,17 @@ fn find_distants_feature(db_path: &str, child_ids: &HashSet<String>, treshold: f
     // finalize_progress_bar();
     let mut avg_failed = 0;
     let mut med_failed = 0;
+    let mut pro_failed = 0;
+    let mut rec_failed = 0;
     let mut num_rec = 0;
     for id in child_ids {
         if let Ok(fs) = FeatureSet::from_db_table(db_path, id) {
             num_rec += fs.atomics.len();
             let distant_from_avgs = fs.find_distant_atomics_from_avg(treshold);
             let distant_from_meds = fs.find_distant_atomics_from_median(treshold);
+            let distant_from_pros = fs.find_distant_atomics_from_prototypes(treshold);
+            let distant_from_recs =
+                fs.find_distant_atomics_from_recent(treshold, DEFAULT_HALF_LIFE_DAYS);
             if distant_from_avgs.len() > 0 {
                 for encd in distant_from_avgs {
                     println!(
@@ -82,36 +113,594 @@ fn find_distants_feature(db_path: &str, child_ids: &HashSet<String>, treshold: f
                     med_failed += 1;
                 }
             }
+            for encd in distant_from_pros {
+                println!(
+                    "From PRO:{}, {}, {}",
+                    encd.child_id, encd.photo_file_name, encd.f_type
+                );
+                pro_failed += 1;
+            }
+            for encd in distant_from_recs {
+                println!(
+                    "From REC:{}, {}, {}",
+                    encd.child_id, encd.photo_file_name, encd.f_type
+                );
+                rec_failed += 1;
+            }
         }
     }
     println!("Total atomic record:{}", num_rec);
     println!(
-        "Total Failed {} => AVG:{}, MED:{}",
-        avg_failed + med_failed,
+        "Total Failed {} => AVG:{}, MED:{}, PRO:{}, REC:{}",
+        avg_failed + med_failed + pro_failed + rec_failed,
         avg_failed,
-        med_failed
+        med_failed,
+        pro_failed,
+        rec_failed
     );
 }
 
+fn find_mixed_identities(db_path: &str, child_ids: &HashSet<String>, threshold: f64) {
+    let mut num_mixed = 0;
+    for id in child_ids {
+        if let Ok(fs) = FeatureSet::from_db_table(db_path, id) {
+            let clusters = fs.find_identity_clusters(threshold, 2);
+            if clusters.is_empty() {
+                continue;
+            }
+            num_mixed += 1;
+            for (i, cluster) in clusters.iter().enumerate() {
+                for encd in cluster {
+                    println!(
+                        "Mixed:{}, cluster {}, {}",
+                        encd.child_id, i, encd.photo_file_name
+                    );
+                }
+            }
+        }
+    }
+    println!("Total children with mixed identities: {}", num_mixed);
+}
+
+fn cluster_unlabeled_photos(db_path: &str) {
+    let intake_path = read_input("Enter intake folder:");
+    let threshold = read_input("Enter threshold:").parse::<f64>().unwrap_or(0.5);
+    let report_path = read_input("Enter report path (default intake_report.csv):");
+    let report_path = if report_path.is_empty() {
+        String::from("intake_report.csv")
+    } else {
+        report_path
+    };
+    let output_dir = read_input("Enter output folder (leave empty to skip copying):");
+
+    match cluster_intake(&intake_path, db_path, threshold) {
+        Ok(clusters) => {
+            for (i, cluster) in clusters.iter().enumerate() {
+                println!("{}: {} faces", cluster.label(i), cluster.faces.len());
+            }
+            if let Err(e) = write_intake_report(&clusters, &report_path) {
+                error!(path = %report_path, error = %e, "Error writing report");
+            } else {
+                println!("Report saved to {}", report_path);
+            }
+            if !output_dir.is_empty() {
+                if let Err(e) = copy_into_folders(&clusters, &output_dir) {
+                    error!(dir = %output_dir, error = %e, "Error copying photos");
+                }
+            }
+        }
+        Err(e) => error!(dir = %intake_path, error = %e, "Error clustering photos"),
+    }
+}
+
+fn evaluate_thresholds(db_path: &str) {
+    let csv_path = read_input("Enter CSV path (default evaluation.csv):");
+    let csv_path = if csv_path.is_empty() {
+        String::from("evaluation.csv")
+    } else {
+        csv_path
+    };
+    let threshold = read_input("Enter threshold to report FAR/FRR at:")
+        .parse::<f64>()
+        .unwrap_or(0.45);
+
+    let gallery = match Gallery::from_db_table(db_path) {
+        Ok(gallery) => gallery,
+        Err(e) => {
+            error!(error = %e, "Error loading gallery");
+            return;
+        }
+    };
+    let evaluations: Vec<Evaluation> = match [
+        Reference::Average,
+        Reference::Median,
+        Reference::Medoid,
+        Reference::Robust,
+    ]
+    .iter()
+    .map(|reference| evaluate(&gallery, *reference, 0.0, 1.0, 0.01))
+    .collect()
+    {
+        Ok(evaluations) => evaluations,
+        Err(e) => {
+            error!(error = %e, "Error evaluating gallery");
+            return;
+        }
+    };
+
+    print!("{}", format_summary_table(&evaluations, threshold));
+    if let Err(e) = write_evaluation_csv(&evaluations, &csv_path) {
+        error!(path = %csv_path, error = %e, "Error writing file");
+    } else {
+        println!("Threshold sweep saved to {}", csv_path);
+    }
+}
+
+fn recompute_child_aggregates(db_path: &str) {
+    let child_id = read_input("Enter child ID (leave empty for all children):");
+    let child_ids = if child_id.is_empty() {
+        match get_all_child_ids(db_path) {
+            Ok(child_ids) => child_ids,
+            Err(e) => {
+                error!(error = %e, "Error reading child IDs");
+                return;
+            }
+        }
+    } else {
+        vec![child_id]
+    };
+
+    let aggregate_config = AggregateConfig {
+        robust: Some(RobustConfig::default()),
+        prototype_count: DEFAULT_PROTOTYPE_COUNT,
+    };
+    init_progress_bar(child_ids.len());
+    set_progress_bar_action("Recomputing", Color::Blue, Style::Bold);
+    for id in &child_ids {
+        if let Err(e) = recompute_aggregates(db_path, id, &aggregate_config) {
+            error!(child_id = %id, error = %e, "Error recomputing aggregates");
+        }
+        inc_progress_bar();
+    }
+    finalize_progress_bar();
+}
+
+fn tag_group_photo(db_path: &str) {
+    let input_path = read_input("Enter photo path:");
+    let threshold = read_input("Enter threshold:")
+        .parse::<f64>()
+        .unwrap_or(0.45);
+    let output_path = read_input("Enter annotated output path (leave empty to skip):");
+
+    let tagger = match Tagger::new(db_path, threshold) {
+        Ok(tagger) => tagger,
+        Err(e) => {
+            error!(error = %e, "Failed to initialize Tagger");
+            return;
+        }
+    };
+    let mut image = match image::open(&input_path) {
+        Ok(image) => image.to_rgb8(),
+        Err(e) => {
+            error!(path = %input_path, error = %e, "Error opening image");
+            return;
+        }
+    };
+
+    let tags = tagger.tag_image(&image);
+    for tag in &tags {
+        println!(
+            "Face:({}, {}, {}, {}), {}, {}",
+            tag.rect.left,
+            tag.rect.top,
+            tag.rect.right,
+            tag.rect.bottom,
+            tag.child_id.as_deref().unwrap_or("unknown"),
+            tag.distance
+                .map(|d| format!("{:.4}", d))
+                .unwrap_or_default()
+        );
+    }
+    println!("Total faces: {}", tags.len());
+
+    if !output_path.is_empty() {
+        render_tags(&mut image, &tags);
+        if let Err(e) = image.save(&output_path) {
+            println!("Error saving the image: {e}");
+        } else {
+            println!("Output image saved to {}", output_path);
+        }
+    }
+}
+
+fn export_distance_matrix(db_path: &str) {
+    let child_id = read_input("Enter child ID (leave empty for the whole gallery):");
+    let output_path = read_input("Enter output path (.csv or .npy):");
+
+    let matrix = if child_id.is_empty() {
+        match Gallery::from_db_table(db_path) {
+            Ok(gallery) => DistanceMatrix::for_gallery(&gallery, Reference::Average),
+            Err(e) => {
+                error!(error = %e, "Error loading gallery");
+                return;
+            }
+        }
+    } else {
+        match FeatureSet::from_db_table(db_path, &child_id) {
+            Ok(fs) => DistanceMatrix::for_child(&fs),
+            Err(e) => {
+                error!(%child_id, error = %e, "Error loading child");
+                return;
+            }
+        }
+    };
+
+    let result = if output_path.to_lowercase().ends_with(".npy") {
+        matrix.write_npy(&output_path)
+    } else {
+        matrix.write_csv(&output_path)
+    };
+    match result {
+        Ok(()) => println!(
+            "{}x{} distance matrix saved to {}",
+            matrix.len(),
+            matrix.len(),
+            output_path
+        ),
+        Err(e) => error!(path = %output_path, error = %e, "Error writing file"),
+    }
+}
+
+fn enroll_single_photo(photo_path: &str, db_path: &str) {
+    let child_id = read_input("Enter child ID:");
+    let file_path = read_input("Enter photo path:");
+
+    match Features::new(photo_path.to_owned(), db_path.to_owned()) {
+        Ok(mut fts) => match fts.enroll_photo(&child_id, &file_path) {
+            Ok(()) => println!("Enrolled {} for child ID {}", file_path, child_id),
+            Err(e) => error!(photo = %file_path, error = %e, "Error enrolling photo"),
+        },
+        Err(e) => error!(error = %e, "Failed to initialize ChildFeatures"),
+    }
+}
+
+fn delete_single_encoding(db_path: &str) {
+    let id = match read_input("Enter face encoding ID:").parse::<i32>() {
+        Ok(id) => id,
+        Err(_) => {
+            println!("Invalid ID");
+            return;
+        }
+    };
+
+    match delete_atomic(db_path, id) {
+        Ok(()) => println!("Deleted face encoding {}", id),
+        Err(e) => error!(id, error = %e, "Error deleting face encoding"),
+    }
+}
+
+fn write_quality_report(db_path: &str) {
+    let prefix = read_input("Enter report path without extension (default quality_report):");
+    let prefix = if prefix.is_empty() {
+        String::from("quality_report")
+    } else {
+        prefix
+    };
+
+    let gallery = match Gallery::from_db_table(db_path) {
+        Ok(gallery) => gallery,
+        Err(e) => {
+            error!(error = %e, "Error loading gallery");
+            return;
+        }
+    };
+    let report = match QualityReport::from_gallery(&gallery, &ReportConfig::default()) {
+        Ok(report) => report,
+        Err(e) => {
+            error!(error = %e, "Error building report");
+            return;
+        }
+    };
+    let text = report.to_text();
+    print!("{}", text);
+
+    let text_path = format!("{}.txt", prefix);
+    if let Err(e) = std::fs::write(&text_path, &text) {
+        error!(path = %text_path, error = %e, "Error writing file");
+    }
+    let json_path = format!("{}.json", prefix);
+    match report.to_json() {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(&json_path, json) {
+                error!(path = %json_path, error = %e, "Error writing file");
+            } else {
+                println!("Report saved to {} and {}", text_path, json_path);
+            }
+        }
+        Err(e) => error!(error = %e, "Error serializing report"),
+    }
+}
+
+fn export_scatter_plot(db_path: &str) {
+    let method = read_input("Projection (pca or tsne, default pca):");
+    let threshold = read_input("Enter outlier threshold (default 0.45):")
+        .parse::<f64>()
+        .unwrap_or(0.45);
+    let output_path = read_input("Enter output path (.svg or .html):");
+
+    let projection = if method.eq_ignore_ascii_case("tsne") {
+        Projection::Tsne {
+            perplexity: 30.0,
+            iterations: 500,
+        }
+    } else {
+        Projection::Pca
+    };
+
+    let gallery = match Gallery::from_db_table(db_path) {
+        Ok(gallery) => gallery,
+        Err(e) => {
+            error!(error = %e, "Error loading gallery");
+            return;
+        }
+    };
+    match scatter_points(&gallery, projection, threshold) {
+        Ok(points) => match write_scatter_plot(&points, &output_path) {
+            Ok(()) => println!(
+                "{} points ({} outliers) saved to {}",
+                points.len(),
+                points.iter().filter(|p| p.outlier).count(),
+                output_path
+            ),
+            Err(e) => error!(path = %output_path, error = %e, "Error writing file"),
+        },
+        Err(e) => error!(error = %e, "Error projecting encodings"),
+    }
+}
+
+fn batch_detect_faces() {
+    let input_dir = read_input("Enter input directory:");
+    let output_dir = read_input("Enter output directory for annotated copies:");
+    let summary_path = format!("{}/face_counts.csv", output_dir.trim_end_matches('/'));
+
+    let detectors = match Detectors::load(&[DetectorSource::Hog, DetectorSource::Cnn]) {
+        Ok(detectors) => detectors,
+        Err(e) => {
+            error!(error = %e, "Error loading detection models");
+            return;
+        }
+    };
+    if let Err(e) = std::fs::create_dir_all(&output_dir) {
+        error!(dir = %output_dir, error = %e, "Error creating directory");
+        return;
+    }
+    let results = detect_directory(&input_dir, &output_dir, &detectors);
+    for result in &results {
+        if let Some(e) = &result.error {
+            error!(image = %result.image_path, error = %e, "Error processing image");
+        }
+    }
+
+    match write_batch_summary(&results, &summary_path) {
+        Ok(()) => println!(
+            "{} images processed, summary saved to {}",
+            results.len(),
+            summary_path
+        ),
+        Err(e) => error!(path = %summary_path, error = %e, "Error writing file"),
+    }
+
+    let images: Vec<ImageDetections> = results
+        .iter()
+        .filter(|result| result.error.is_none())
+        .map(|result| {
+            ImageDetections::new(
+                &result.image_path,
+                result.width,
+                result.height,
+                &result.detections,
+            )
+        })
+        .collect();
+    let output_dir = output_dir.trim_end_matches('/');
+    let json_path = format!("{}/detections.json", output_dir);
+    let coco_path = format!("{}/detections_coco.json", output_dir);
+    if let Err(e) = write_json(&images, &json_path).and_then(|_| write_coco(&images, &coco_path)) {
+        error!(error = %e, "Error exporting detections");
+    } else {
+        println!("Detections exported to {} and {}", json_path, coco_path);
+    }
+}
+
+fn export_face_chips() {
+    let photo_path = read_input("Enter photo path:");
+    let output_dir = read_input("Enter output directory for chips:");
+
+    let detectors = match Detectors::load(&[DetectorSource::Cnn]) {
+        Ok(detectors) => detectors,
+        Err(e) => {
+            error!(error = %e, "Error loading detection models");
+            return;
+        }
+    };
+    match save_chips(&photo_path, &output_dir, &detectors, &ChipConfig::default()) {
+        Ok(paths) => println!("{} face chips saved to {}", paths.len(), output_dir),
+        Err(e) => error!(source = %photo_path, error = %e, "Error exporting chips"),
+    }
+}
+
+fn generate_contact_sheet(photo_path: &str, db_path: &str) {
+    let child_id = read_input("Enter child ID:");
+    let threshold = read_input("Enter threshold (default 0.45):")
+        .parse::<f64>()
+        .unwrap_or(0.45);
+    let output_path = read_input("Enter output image path:");
+
+    let fs = match FeatureSet::from_db_table(db_path, &child_id) {
+        Ok(fs) => fs,
+        Err(e) => {
+            error!(%child_id, error = %e, "Error loading child");
+            return;
+        }
+    };
+    let detectors = match Detectors::load(&[DetectorSource::Cnn]) {
+        Ok(detectors) => detectors,
+        Err(e) => {
+            error!(error = %e, "Error loading detection models");
+            return;
+        }
+    };
+    let config = SheetConfig {
+        threshold,
+        ..SheetConfig::default()
+    };
+
+    let entries = contact_sheet_entries(&fs, &config);
+    let sheet = render_contact_sheet(&entries, photo_path, &detectors, &config);
+    match sheet.save(&output_path) {
+        Ok(()) => println!(
+            "Contact sheet of {} photos ({} outliers) saved to {}",
+            entries.len(),
+            entries.iter().filter(|e| e.outlier).count(),
+            output_path
+        ),
+        Err(e) => error!(path = %output_path, error = %e, "Error saving file"),
+    }
+}
+
+fn write_review_report(photo_path: &str, db_path: &str) {
+    let threshold = read_input("Enter threshold (default 0.45):")
+        .parse::<f64>()
+        .unwrap_or(0.45);
+    let output_path = read_input("Enter output path (default review.html):");
+    let output_path = if output_path.is_empty() {
+        String::from("review.html")
+    } else {
+        output_path
+    };
+
+    let gallery = match Gallery::from_db_table(db_path) {
+        Ok(gallery) => gallery,
+        Err(e) => {
+            error!(error = %e, "Error loading gallery");
+            return;
+        }
+    };
+    let detectors = match Detectors::load(&[DetectorSource::Cnn]) {
+        Ok(detectors) => detectors,
+        Err(e) => {
+            error!(error = %e, "Error loading detection models");
+            return;
+        }
+    };
+
+    let reviews = build_review(&gallery, threshold);
+    let html = render_review_html(&reviews, photo_path, &detectors, threshold);
+    match std::fs::write(&output_path, html) {
+        Ok(()) => println!(
+            "Review of {} children saved to {}",
+            reviews.len(),
+            output_path
+        ),
+        Err(e) => error!(path = %output_path, error = %e, "Error writing file"),
+    }
+}
+
+fn benchmark_face_detectors() {
+    let input_dir = read_input("Enter image directory:");
+    let csv_path = read_input("Enter per-image CSV path (default benchmark.csv):");
+    let csv_path = if csv_path.is_empty() {
+        String::from("benchmark.csv")
+    } else {
+        csv_path
+    };
+
+    match benchmark_detectors(&input_dir) {
+        Ok(results) => {
+            print!("{}", format_benchmark_summary(&results));
+            match write_benchmark_csv(&results, &csv_path) {
+                Ok(()) => println!("Per-image results saved to {}", csv_path),
+                Err(e) => error!(path = %csv_path, error = %e, "Error writing file"),
+            }
+        }
+        Err(e) => error!(error = %e, "Error running benchmark"),
+    }
+}
+
+fn watch_photo_directory(photo_path: &str, db_path: &str) {
+    match Features::new(photo_path.to_owned(), db_path.to_owned()) {
+        Ok(mut fts) => {
+            println!(
+                "Watching {} for new photos, press Ctrl+C to stop",
+                photo_path
+            );
+            if let Err(e) = watch_and_enroll(&mut fts, photo_path, Duration::from_secs(2)) {
+                error!(dir = %photo_path, error = %e, "Error watching directory");
+            }
+        }
+        Err(e) => error!(error = %e, "Error loading models"),
+    }
+}
+
+// A photo directory is rescanned on every call so newly added photos are picked
+// up. Listing an archive means decompressing it, so its IDs are read only once.
+fn current_child_ids(
+    photo_path: &str,
+    archive_child_ids: &mut Option<HashSet<String>>,
+) -> HashSet<String> {
+    if !is_archive(photo_path) {
+        return extract_unique_child_ids(photo_path);
+    }
+    archive_child_ids
+        .get_or_insert_with(|| extract_unique_child_ids(photo_path))
+        .clone()
+}
+
+fn read_input(prompt: &str) -> String {
+    println!("{}", prompt);
+    io::stdout().flush().unwrap();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
+    input.trim().to_string()
+}
+
 fn main() {
+    init_logging(LogFormat::from_env());
     let db_path = String::from("dataset.db");
     let photo_path = String::from("/Users/ek_solution/Downloads/photos");
+    let mut archive_child_ids: Option<HashSet<String>> = None;
 
     loop {
         println!("Select an option:");
         println!("1. Extract Photos");
         println!("2. Find distant features");
-        println!("3. Exit");
+        println!("3. Find mixed identities");
+        println!("4. Cluster unlabeled photos");
+        println!("5. Evaluate thresholds");
+        println!("6. Recompute aggregates");
+        println!("7. Tag group photo");
+        println!("8. Export distance matrix");
+        println!("9. Enroll single photo");
+        println!("10. Delete face encoding");
+        println!("11. Data quality report");
+        println!("12. Export scatter plot");
+        println!("13. Batch detect faces");
+        println!("14. Export aligned face chips");
+        println!("15. Generate contact sheet");
+        println!("16. Write HTML review report");
+        println!("17. Benchmark face detectors");
+        println!("18. Watch photo directory");
+        println!("19. Exit");
         print!("Enter your choice: ");
         io::stdout().flush().unwrap(); // Make sure the prompt is displayed
 
         let mut choice = String::new();
         io::stdin().read_line(&mut choice).unwrap();
         let choice = choice.trim();
-        let child_ids = extract_unique_child_ids(&photo_path);
 
         match choice {
             "1" => {
+                let child_ids = current_child_ids(&photo_path, &mut archive_child_ids);
                 extract_photos(&photo_path, &db_path, &child_ids);
             }
             "2" => {
@@ -121,9 +710,60 @@ fn main() {
                 let mut choice2 = String::new();
                 io::stdin().read_line(&mut choice2).unwrap();
                 let treshold = choice2.parse::<f64>().unwrap_or_else(|_| 0.45);
+                let child_ids = current_child_ids(&photo_path, &mut archive_child_ids);
                 find_distants_feature(&db_path, &child_ids, treshold);
             }
-            "3" => break,
+            "3" => {
+                let threshold = read_input("Enter threshold:").parse::<f64>().unwrap_or(0.5);
+                let child_ids = current_child_ids(&photo_path, &mut archive_child_ids);
+                find_mixed_identities(&db_path, &child_ids, threshold);
+            }
+            "4" => {
+                cluster_unlabeled_photos(&db_path);
+            }
+            "5" => {
+                evaluate_thresholds(&db_path);
+            }
+            "6" => {
+                recompute_child_aggregates(&db_path);
+            }
+            "7" => {
+                tag_group_photo(&db_path);
+            }
+            "8" => {
+                export_distance_matrix(&db_path);
+            }
+            "9" => {
+                enroll_single_photo(&photo_path, &db_path);
+            }
+            "10" => {
+                delete_single_encoding(&db_path);
+            }
+            "11" => {
+                write_quality_report(&db_path);
+            }
+            "12" => {
+                export_scatter_plot(&db_path);
+            }
+            "13" => {
+                batch_detect_faces();
+            }
+            "14" => {
+                export_face_chips();
+            }
+            "15" => {
+                generate_contact_sheet(&photo_path, &db_path);
+            }
+            "16" => {
+                write_review_report(&photo_path, &db_path);
+            }
+            "17" => {
+                benchmark_face_detectors();
+            }
+            "18" => {
+                watch_photo_directory(&photo_path, &db_path);
+            }
+            "19" => break,
             _ => println!("Invalid choice, please try again."),
         }
     }
@@ -1,33 +1,152 @@
-use std::collections::HashSet;
+use crate::archive::{is_archive, list_archive_entries};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use exif::{In, Tag, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor, Seek};
 use std::path::Path;
 use walkdir::WalkDir;
 
+// Capture dates are stored in SQLite's DATETIME text format
+pub const CAPTURE_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+// `dir_path` may also be a .zip or .tar(.gz) archive of photos
 pub fn extract_unique_child_ids(dir_path: &str) -> HashSet<String> {
     let mut child_ids = HashSet::new();
 
+    if is_archive(dir_path) {
+        match list_archive_entries(dir_path) {
+            Ok(names) => {
+                for name in names {
+                    child_ids.extend(photo_child_id(Path::new(&name)));
+                }
+            }
+            Err(e) => tracing::error!(archive = dir_path, error = %e, "Error reading archive"),
+        }
+        return child_ids;
+    }
+
     for entry in WalkDir::new(dir_path)
         .into_iter()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_file() && is_image_file(e.path()))
+        .filter(|e| e.path().is_file())
     {
-        if let Some(child_id) = extract_child_id_from_filename(entry.path()) {
-            child_ids.insert(child_id);
-        }
+        child_ids.extend(photo_child_id(entry.path()));
     }
 
     child_ids
 }
 
-fn is_image_file(path: &Path) -> bool {
+pub fn list_image_files(dir_path: &str) -> Vec<String> {
+    WalkDir::new(dir_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file() && is_image_file(e.path()))
+        .filter_map(|e| e.path().to_str().map(String::from))
+        .collect()
+}
+
+// Maps each image's file name to its full path, as atomics only store the name.
+// A name found in several subdirectories is ambiguous, so it is logged and left out.
+pub fn index_photos_by_name(dir_path: &str) -> HashMap<String, String> {
+    let mut index: HashMap<String, String> = HashMap::new();
+    let mut ambiguous: HashSet<String> = HashSet::new();
+
+    for path in list_image_files(dir_path) {
+        let Some(name) = Path::new(&path).file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if let Some(existing) = index.get(name) {
+            tracing::warn!(photo = name, first = %existing, second = %path, "Duplicate photo file name");
+            ambiguous.insert(name.to_string());
+        } else {
+            index.insert(name.to_string(), path.clone());
+        }
+    }
+    index.retain(|name, _| !ambiguous.contains(name));
+    index
+}
+
+pub(crate) fn is_image_file(path: &Path) -> bool {
     path.extension()
         .and_then(|ext| ext.to_str())
         .map(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("png"))
         .unwrap_or(false)
 }
 
-fn extract_child_id_from_filename(path: &Path) -> Option<String> {
+// Child ID of a .jpg/.png named `<childID>_...`. Every photo source matches
+// files to children through this, so they all agree on which photos belong to whom.
+pub(crate) fn photo_child_id(path: &Path) -> Option<String> {
+    if !is_image_file(path) {
+        return None;
+    }
+    extract_child_id_from_filename(path)
+}
+
+pub(crate) fn extract_child_id_from_filename(path: &Path) -> Option<String> {
     path.file_stem()
         .and_then(|stem| stem.to_str())
         .and_then(|stem| stem.split('_').next())
         .map(|child_id| child_id.to_string())
 }
+
+// When the photo was taken: EXIF DateTimeOriginal, falling back to the file's mtime
+pub fn read_capture_date(photo_path: &str) -> Option<NaiveDateTime> {
+    read_exif_capture_date(photo_path).or_else(|| {
+        fs::metadata(photo_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| DateTime::<Local>::from(modified).naive_local())
+    })
+}
+
+// Same for an encoded image held in memory, e.g. an archive entry, falling back to `modified`
+pub fn read_capture_date_from_bytes(
+    bytes: &[u8],
+    modified: Option<NaiveDateTime>,
+) -> Option<NaiveDateTime> {
+    read_exif_date(&mut Cursor::new(bytes)).or(modified)
+}
+
+fn read_exif_capture_date(photo_path: &str) -> Option<NaiveDateTime> {
+    let file = File::open(photo_path).ok()?;
+    read_exif_date(&mut BufReader::new(file))
+}
+
+fn read_exif_date(reader: &mut (impl BufRead + Seek)) -> Option<NaiveDateTime> {
+    let exif = exif::Reader::new().read_from_container(reader).ok()?;
+    let field = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY))?;
+
+    match &field.value {
+        Value::Ascii(values) => {
+            let dt = exif::DateTime::from_ascii(values.first()?).ok()?;
+            NaiveDate::from_ymd_opt(dt.year as i32, dt.month as u32, dt.day as u32)?.and_hms_opt(
+                dt.hour as u32,
+                dt.minute as u32,
+                dt.second as u32,
+            )
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_id_must_match_the_whole_prefix() {
+        assert_eq!(
+            photo_child_id(Path::new("in/A12_1.jpg")).as_deref(),
+            Some("A12")
+        );
+        assert_eq!(photo_child_id(Path::new("A1_2.PNG")).as_deref(), Some("A1"));
+        assert_ne!(
+            photo_child_id(Path::new("A12_1.jpg")).as_deref(),
+            Some("A1")
+        );
+        assert_eq!(photo_child_id(Path::new("A12_1.txt")), None);
+    }
+}
@@ -0,0 +1,365 @@
+use crate::compare::Gallery;
+use crate::error::StatsError;
+use crate::stats::{compute_average, compute_covariance};
+use crate::tool::escape_xml;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use tracing::warn;
+
+// t-SNE holds two n x n matrices (about 144 MB at this size); larger sets use PCA
+pub const MAX_TSNE_POINTS: usize = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    Pca,
+    // Exact t-SNE seeded from PCA; O(n^2) time and memory, so capped at MAX_TSNE_POINTS
+    Tsne { perplexity: f64, iterations: usize },
+}
+
+#[derive(Debug, Clone)]
+pub struct ScatterPoint {
+    pub child_id: String,
+    pub photo_file_name: String,
+    pub x: f64,
+    pub y: f64,
+    // Further than the outlier threshold from the child's average
+    pub outlier: bool,
+}
+
+/// Projects every feature onto the first two principal components.
+pub fn project_pca(features: &[Vec<f64>]) -> Result<Vec<[f64; 2]>, StatsError> {
+    let mean = compute_average(features)?;
+    let mut covariance = compute_covariance(features)?;
+
+    let first = principal_component(&covariance);
+    // Deflate so the second power iteration converges on the next component
+    let eigenvalue = rayleigh_quotient(&covariance, &first);
+    for (i, row) in covariance.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell -= eigenvalue * first[i] * first[j];
+        }
+    }
+    let second = principal_component(&covariance);
+
+    Ok(features
+        .iter()
+        .map(|feature| {
+            let centred: Vec<f64> = feature.iter().zip(&mean).map(|(v, m)| v - m).collect();
+            [dot(&centred, &first), dot(&centred, &second)]
+        })
+        .collect())
+}
+
+/// Projects features to 2D with t-SNE, starting from the PCA layout so the
+/// result is deterministic. Returns the PCA layout, with a warning, for more
+/// than `MAX_TSNE_POINTS` features.
+pub fn project_tsne(
+    features: &[Vec<f64>],
+    perplexity: f64,
+    iterations: usize,
+) -> Result<Vec<[f64; 2]>, StatsError> {
+    let initial = project_pca(features)?;
+    let n = features.len();
+    if n < 3 {
+        return Ok(initial);
+    }
+    if n > MAX_TSNE_POINTS {
+        warn!(
+            points = n,
+            max = MAX_TSNE_POINTS,
+            "Too many points for t-SNE, falling back to PCA"
+        );
+        return Ok(initial);
+    }
+
+    let p = joint_probabilities(features, perplexity.min((n - 1) as f64 / 3.0));
+
+    // Shrink the PCA layout so early iterations aren't dominated by it
+    let scale = initial
+        .iter()
+        .map(|y| y[0].abs().max(y[1].abs()))
+        .fold(0.0, f64::max)
+        .max(f64::EPSILON);
+    let mut y: Vec<[f64; 2]> = initial
+        .iter()
+        .map(|p| [p[0] / scale * 1e-2, p[1] / scale * 1e-2])
+        .collect();
+    let mut velocity = vec![[0.0; 2]; n];
+    let learning_rate = 200.0;
+
+    for iteration in 0..iterations {
+        let exaggeration = if iteration < 100 { 12.0 } else { 1.0 };
+        let momentum = if iteration < 250 { 0.5 } else { 0.8 };
+
+        let mut q = vec![0.0; n * n];
+        let mut q_sum = 0.0;
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let dx = y[i][0] - y[j][0];
+                let dy = y[i][1] - y[j][1];
+                let value = 1.0 / (1.0 + dx * dx + dy * dy);
+                q[i * n + j] = value;
+                q[j * n + i] = value;
+                q_sum += 2.0 * value;
+            }
+        }
+
+        for i in 0..n {
+            let mut gradient = [0.0; 2];
+            for j in 0..n {
+                if i == j {
+                    continue;
+                }
+                let unnormalised = q[i * n + j];
+                let force = (exaggeration * p[i * n + j] - unnormalised / q_sum) * unnormalised;
+                gradient[0] += 4.0 * force * (y[i][0] - y[j][0]);
+                gradient[1] += 4.0 * force * (y[i][1] - y[j][1]);
+            }
+            for d in 0..2 {
+                velocity[i][d] = momentum * velocity[i][d] - learning_rate * gradient[d];
+            }
+        }
+        for (point, step) in y.iter_mut().zip(&velocity) {
+            point[0] += step[0];
+            point[1] += step[1];
+        }
+    }
+
+    Ok(y)
+}
+
+/// Projects every atomic in the gallery and flags those further than
+/// `outlier_threshold` from their child's average.
+pub fn scatter_points(
+    gallery: &Gallery,
+    projection: Projection,
+    outlier_threshold: f64,
+) -> Result<Vec<ScatterPoint>, String> {
+    let mut points = Vec::new();
+    let mut vectors = Vec::new();
+
+    for fs in &gallery.feature_sets {
+        let outlier_ids: HashSet<i32> = fs
+            .find_distant_atomics_from_avg(outlier_threshold)
+            .iter()
+            .map(|a| a.id)
+            .collect();
+        for atomic in &fs.atomics {
+            points.push(ScatterPoint {
+                child_id: atomic.child_id.clone(),
+                photo_file_name: atomic.photo_file_name.clone(),
+                x: 0.0,
+                y: 0.0,
+                outlier: outlier_ids.contains(&atomic.id),
+            });
+            vectors.push(atomic.feature_vector.clone());
+        }
+    }
+
+    let coordinates = match projection {
+        Projection::Pca => project_pca(&vectors),
+        Projection::Tsne {
+            perplexity,
+            iterations,
+        } => project_tsne(&vectors, perplexity, iterations),
+    }
+    .map_err(|e| e.to_string())?;
+
+    for (point, [x, y]) in points.iter_mut().zip(coordinates) {
+        point.x = x;
+        point.y = y;
+    }
+    Ok(points)
+}
+
+/// Renders the points as an SVG scatter plot, one colour per child. Outliers
+/// get a thick black ring; hovering a point shows its child ID and file name.
+pub fn render_scatter_svg(points: &[ScatterPoint]) -> String {
+    const SIZE: f64 = 800.0;
+    const MARGIN: f64 = 20.0;
+
+    let (min_x, max_x) = bounds(points.iter().map(|p| p.x));
+    let (min_y, max_y) = bounds(points.iter().map(|p| p.y));
+    let span_x = (max_x - min_x).max(f64::EPSILON);
+    let span_y = (max_y - min_y).max(f64::EPSILON);
+
+    let mut child_ids: Vec<&str> = points.iter().map(|p| p.child_id.as_str()).collect();
+    child_ids.sort();
+    child_ids.dedup();
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{0}\" viewBox=\"0 0 {0} {0}\">",
+        SIZE
+    );
+    let _ = writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>");
+
+    // Outliers last so their rings aren't hidden under other points
+    let ordered = points
+        .iter()
+        .filter(|p| !p.outlier)
+        .chain(points.iter().filter(|p| p.outlier));
+    for point in ordered {
+        let cx = MARGIN + (point.x - min_x) / span_x * (SIZE - 2.0 * MARGIN);
+        // SVG y grows downwards
+        let cy = SIZE - MARGIN - (point.y - min_y) / span_y * (SIZE - 2.0 * MARGIN);
+        let index = child_ids
+            .binary_search(&point.child_id.as_str())
+            .unwrap_or(0);
+        let (radius, stroke) = if point.outlier {
+            (6.0, "stroke=\"black\" stroke-width=\"2\"")
+        } else {
+            (4.0, "stroke=\"none\"")
+        };
+        let _ = writeln!(
+            svg,
+            "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\" fill-opacity=\"0.8\" {}><title>{}: {}{}</title></circle>",
+            cx,
+            cy,
+            radius,
+            colour(index),
+            stroke,
+            escape_xml(&point.child_id),
+            escape_xml(&point.photo_file_name),
+            if point.outlier { " (outlier)" } else { "" }
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+// Writes a standalone HTML page when the path ends in .html, otherwise a bare SVG
+pub fn write_scatter_plot(points: &[ScatterPoint], output_path: &str) -> Result<(), String> {
+    let svg = render_scatter_svg(points);
+    let contents = if output_path.to_lowercase().ends_with(".html") {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Face encodings</title></head>\n<body>\n<p>{} photos. Hover a point for its file name; ringed points are outliers.</p>\n{}</body>\n</html>\n",
+            points.len(),
+            svg
+        )
+    } else {
+        svg
+    };
+    fs::write(output_path, contents).map_err(|e| e.to_string())
+}
+
+// Symmetric t-SNE affinities, with each row's bandwidth tuned to the perplexity
+fn joint_probabilities(features: &[Vec<f64>], perplexity: f64) -> Vec<f64> {
+    let n = features.len();
+    let target_entropy = perplexity.ln();
+    let mut distances = vec![0.0; n * n];
+    for i in 0..n {
+        for j in (i + 1)..n {
+            let d: f64 = features[i]
+                .iter()
+                .zip(&features[j])
+                .map(|(a, b)| (a - b).powi(2))
+                .sum();
+            distances[i * n + j] = d;
+            distances[j * n + i] = d;
+        }
+    }
+
+    let mut conditional = vec![0.0; n * n];
+    for i in 0..n {
+        let row = &distances[i * n..(i + 1) * n];
+        // Binary search on the precision beta = 1 / (2 sigma^2)
+        let (mut beta, mut low, mut high) = (1.0, 0.0, f64::INFINITY);
+        let mut probabilities = vec![0.0; n];
+        for _ in 0..50 {
+            let mut sum = 0.0;
+            for j in 0..n {
+                probabilities[j] = if i == j { 0.0 } else { (-row[j] * beta).exp() };
+                sum += probabilities[j];
+            }
+            let sum = sum.max(f64::MIN_POSITIVE);
+            let weighted: f64 = (0..n).map(|j| row[j] * probabilities[j]).sum();
+            let entropy = sum.ln() + beta * weighted / sum;
+            for probability in probabilities.iter_mut() {
+                *probability /= sum;
+            }
+
+            if (entropy - target_entropy).abs() < 1e-5 {
+                break;
+            }
+            if entropy > target_entropy {
+                low = beta;
+                beta = if high.is_finite() {
+                    (beta + high) / 2.0
+                } else {
+                    beta * 2.0
+                };
+            } else {
+                high = beta;
+                beta = (beta + low) / 2.0;
+            }
+        }
+        conditional[i * n..(i + 1) * n].copy_from_slice(&probabilities);
+    }
+
+    let mut joint = vec![0.0; n * n];
+    for i in 0..n {
+        for j in 0..n {
+            joint[i * n + j] =
+                ((conditional[i * n + j] + conditional[j * n + i]) / (2.0 * n as f64)).max(1e-12);
+        }
+    }
+    joint
+}
+
+// Dominant eigenvector of a symmetric matrix by power iteration
+fn principal_component(matrix: &[Vec<f64>]) -> Vec<f64> {
+    let dim = matrix.len();
+    // Not all ones: that can be orthogonal to the component for centred data
+    let mut vector: Vec<f64> = (0..dim).map(|i| 1.0 + i as f64 / dim as f64).collect();
+    normalise(&mut vector);
+
+    for _ in 0..200 {
+        let mut next: Vec<f64> = matrix.iter().map(|row| dot(row, &vector)).collect();
+        if !normalise(&mut next) {
+            // Zero matrix, e.g. a single photo: any direction is as good as another
+            return vec![0.0; dim];
+        }
+        let change: f64 = next.iter().zip(&vector).map(|(a, b)| (a - b).abs()).sum();
+        vector = next;
+        if change < 1e-9 {
+            break;
+        }
+    }
+    vector
+}
+
+fn rayleigh_quotient(matrix: &[Vec<f64>], vector: &[f64]) -> f64 {
+    let product: Vec<f64> = matrix.iter().map(|row| dot(row, vector)).collect();
+    dot(&product, vector)
+}
+
+// Scales to unit length; false if the vector is (numerically) zero
+fn normalise(vector: &mut [f64]) -> bool {
+    let norm = dot(vector, vector).sqrt();
+    if norm < 1e-12 {
+        return false;
+    }
+    for v in vector.iter_mut() {
+        *v /= norm;
+    }
+    true
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn bounds(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+// Golden-angle hue steps keep consecutive child IDs visually distinct
+fn colour(index: usize) -> String {
+    let hue = (index * 137) % 360;
+    format!("hsl({}, 70%, 50%)", hue)
+}
@@ -0,0 +1,341 @@
+use crate::compare::{euclidean_distance, Gallery, Reference};
+use crate::distance::DistanceMatrix;
+use crate::stats::{compute_spread, Spread};
+use serde::Serialize;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReportConfig {
+    // Children with fewer atomics than this are listed as under-enrolled
+    pub min_atomics: usize,
+    // Length of the closest-pairs and largest-spread lists
+    pub top_n: usize,
+    pub bin_width: f64,
+    // Distances at or above this all land in the last histogram bin
+    pub max_distance: f64,
+}
+
+// Upper bound on histogram bins, so a tiny bin width can't exhaust memory
+const MAX_HISTOGRAM_BINS: usize = 10_000;
+
+impl ReportConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.bin_width.is_finite() && self.bin_width > 0.0) {
+            return Err(format!(
+                "Histogram bin width must be positive, got {}",
+                self.bin_width
+            ));
+        }
+        if !(self.max_distance.is_finite() && self.max_distance >= self.bin_width) {
+            return Err(format!(
+                "Histogram max distance {} must be at least the bin width {}",
+                self.max_distance, self.bin_width
+            ));
+        }
+        if self.max_distance / self.bin_width > MAX_HISTOGRAM_BINS as f64 {
+            return Err(format!(
+                "Histogram would need more than {} bins; widen the bins or lower the max distance",
+                MAX_HISTOGRAM_BINS
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig {
+            min_atomics: 5,
+            top_n: 10,
+            bin_width: 0.05,
+            max_distance: 1.2,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CountBucket {
+    pub label: String,
+    pub children: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Histogram {
+    pub bin_width: f64,
+    // counts[i] covers [i * bin_width, (i + 1) * bin_width)
+    pub counts: Vec<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChildCount {
+    pub child_id: String,
+    pub atomics: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChildPair {
+    pub child_a: String,
+    pub child_b: String,
+    pub distance: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChildSpread {
+    pub child_id: String,
+    pub spread: Spread,
+}
+
+/// Database-wide summary of enrollment coverage and embedding quality.
+#[derive(Debug, Serialize)]
+pub struct QualityReport {
+    pub total_children: usize,
+    // Children left out because their rows failed to load
+    pub skipped_children: Vec<String>,
+    pub total_atomics: usize,
+    pub photo_count_buckets: Vec<CountBucket>,
+    pub under_enrolled: Vec<ChildCount>,
+    // Each atomic against its own child's average
+    pub intra_child: Histogram,
+    // Each pair of child averages
+    pub inter_child: Histogram,
+    // Look-alike risk: the closest child averages
+    pub closest_pairs: Vec<ChildPair>,
+    pub largest_spread: Vec<ChildSpread>,
+}
+
+const BUCKETS: [(usize, usize, &str); 6] = [
+    (1, 1, "1"),
+    (2, 4, "2-4"),
+    (5, 9, "5-9"),
+    (10, 19, "10-19"),
+    (20, 49, "20-49"),
+    (50, usize::MAX, "50+"),
+];
+
+impl QualityReport {
+    pub fn from_gallery(gallery: &Gallery, config: &ReportConfig) -> Result<Self, String> {
+        config.validate()?;
+        let fss = &gallery.feature_sets;
+
+        let photo_count_buckets = BUCKETS
+            .iter()
+            .map(|(low, high, label)| CountBucket {
+                label: label.to_string(),
+                children: fss
+                    .iter()
+                    .filter(|fs| fs.atomics.len() >= *low && fs.atomics.len() <= *high)
+                    .count(),
+            })
+            .collect();
+
+        let mut under_enrolled: Vec<ChildCount> = fss
+            .iter()
+            .filter(|fs| fs.atomics.len() < config.min_atomics)
+            .map(|fs| ChildCount {
+                child_id: fs.average.child_id.clone(),
+                atomics: fs.atomics.len(),
+            })
+            .collect();
+        under_enrolled.sort_by_key(|c| c.atomics);
+
+        let intra_distances: Vec<f64> = fss
+            .iter()
+            .flat_map(|fs| {
+                fs.atomics
+                    .iter()
+                    .map(|a| euclidean_distance(&a.feature_vector, &fs.average.feature_vector))
+            })
+            .collect();
+
+        let matrix = DistanceMatrix::for_gallery(gallery, Reference::Average);
+        let mut pairs: Vec<ChildPair> = Vec::new();
+        for i in 0..matrix.len() {
+            for j in (i + 1)..matrix.len() {
+                pairs.push(ChildPair {
+                    child_a: matrix.labels[i].clone(),
+                    child_b: matrix.labels[j].clone(),
+                    distance: matrix.get(i, j),
+                });
+            }
+        }
+        let inter_distances: Vec<f64> = pairs.iter().map(|p| p.distance).collect();
+        pairs.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        pairs.truncate(config.top_n);
+
+        let mut largest_spread: Vec<ChildSpread> = fss
+            .iter()
+            .filter_map(|fs| {
+                let vectors: Vec<Vec<f64>> = fs
+                    .atomics
+                    .iter()
+                    .map(|a| a.feature_vector.clone())
+                    .collect();
+                compute_spread(&vectors).ok().map(|spread| ChildSpread {
+                    child_id: fs.average.child_id.clone(),
+                    spread,
+                })
+            })
+            .collect();
+        largest_spread.sort_by(|a, b| {
+            b.spread
+                .mean_distance_to_centroid
+                .total_cmp(&a.spread.mean_distance_to_centroid)
+        });
+        largest_spread.truncate(config.top_n);
+
+        Ok(QualityReport {
+            total_children: fss.len(),
+            skipped_children: gallery.skipped.clone(),
+            total_atomics: fss.iter().map(|fs| fs.atomics.len()).sum(),
+            photo_count_buckets,
+            under_enrolled,
+            intra_child: histogram(&intra_distances, config)?,
+            inter_child: histogram(&inter_distances, config)?,
+            closest_pairs: pairs,
+            largest_spread,
+        })
+    }
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| e.to_string())
+    }
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "Children: {}", self.total_children);
+        if !self.skipped_children.is_empty() {
+            let _ = writeln!(
+                text,
+                "Skipped children (failed to load): {} ({})",
+                self.skipped_children.len(),
+                self.skipped_children.join(", ")
+            );
+        }
+        let _ = writeln!(text, "Atomic records: {}", self.total_atomics);
+
+        let _ = writeln!(text, "\nChildren per photo count:");
+        for bucket in &self.photo_count_buckets {
+            let _ = writeln!(text, "  {:>6}: {}", bucket.label, bucket.children);
+        }
+
+        let _ = writeln!(
+            text,
+            "\nUnder-enrolled children: {}",
+            self.under_enrolled.len()
+        );
+        for child in &self.under_enrolled {
+            let _ = writeln!(text, "  {}: {} atomics", child.child_id, child.atomics);
+        }
+
+        let _ = writeln!(text, "\nIntra-child distances (atomic to own average):");
+        text.push_str(&format_histogram(&self.intra_child));
+        let _ = writeln!(text, "\nInter-child distances (average to average):");
+        text.push_str(&format_histogram(&self.inter_child));
+
+        let _ = writeln!(text, "\nClosest pairs of children:");
+        for pair in &self.closest_pairs {
+            let _ = writeln!(
+                text,
+                "  {} - {}: {:.4}",
+                pair.child_a, pair.child_b, pair.distance
+            );
+        }
+
+        let _ = writeln!(text, "\nLargest spread:");
+        for child in &self.largest_spread {
+            let _ = writeln!(
+                text,
+                "  {}: mean {:.4}, max {:.4}, pairwise {:.4} ({} atomics)",
+                child.child_id,
+                child.spread.mean_distance_to_centroid,
+                child.spread.max_distance_to_centroid,
+                child.spread.mean_pairwise_distance,
+                child.spread.count
+            );
+        }
+        text
+    }
+}
+
+fn histogram(distances: &[f64], config: &ReportConfig) -> Result<Histogram, String> {
+    config.validate()?;
+    let num_bins = (config.max_distance / config.bin_width).ceil() as usize;
+    let mut counts = vec![0; num_bins];
+
+    for distance in distances {
+        let bin = ((distance / config.bin_width) as usize).min(num_bins - 1);
+        counts[bin] += 1;
+    }
+
+    Ok(Histogram {
+        bin_width: config.bin_width,
+        counts,
+    })
+}
+
+fn format_histogram(histogram: &Histogram) -> String {
+    const BAR_WIDTH: usize = 50;
+    let max = histogram.counts.iter().cloned().max().unwrap_or(0).max(1);
+    let mut text = String::new();
+
+    for (i, count) in histogram.counts.iter().enumerate() {
+        let bar = "#".repeat(count * BAR_WIDTH / max);
+        let _ = writeln!(
+            text,
+            "  {:.2}-{:.2} {:>7} {}",
+            i as f64 * histogram.bin_width,
+            (i + 1) as f64 * histogram.bin_width,
+            count,
+            bar
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_bin_widths_are_rejected() {
+        for bin_width in [0.0, -0.05, f64::NAN, f64::INFINITY] {
+            let config = ReportConfig {
+                bin_width,
+                ..ReportConfig::default()
+            };
+            assert!(
+                histogram(&[0.1], &config).is_err(),
+                "bin width {}",
+                bin_width
+            );
+        }
+    }
+
+    #[test]
+    fn max_distance_must_cover_one_bin() {
+        let config = ReportConfig {
+            bin_width: 0.5,
+            max_distance: 0.1,
+            ..ReportConfig::default()
+        };
+        assert!(histogram(&[0.1], &config).is_err());
+    }
+
+    #[test]
+    fn huge_bin_counts_are_rejected() {
+        let config = ReportConfig {
+            bin_width: 1e-12,
+            ..ReportConfig::default()
+        };
+        assert!(histogram(&[0.1], &config).is_err());
+    }
+
+    #[test]
+    fn distances_past_the_max_land_in_the_last_bin() {
+        let config = ReportConfig {
+            bin_width: 0.5,
+            max_distance: 1.0,
+            ..ReportConfig::default()
+        };
+        let histogram = histogram(&[0.1, 0.6, 0.7, 5.0], &config).unwrap();
+        assert_eq!(histogram.counts, vec![1, 3]);
+    }
+}
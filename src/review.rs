@@ -0,0 +1,162 @@
+use crate::chip::face_thumbnails;
+use crate::compare::{euclidean_distance, Gallery};
+use crate::detect::Detectors;
+use crate::tool::escape_xml;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use image::{ImageOutputFormat, RgbImage};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::Cursor;
+
+#[derive(Debug, Clone)]
+pub struct FlaggedPhoto {
+    pub photo_file_name: String,
+    pub distance_to_average: f64,
+    pub distance_to_median: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChildReview {
+    pub child_id: String,
+    // The child's most typical photo, shown for comparison
+    pub medoid_photo: String,
+    pub flagged: Vec<FlaggedPhoto>,
+}
+
+/// Children with at least one atomic further than `threshold` from either
+/// their average or their median, flagged photos furthest first.
+pub fn build_review(gallery: &Gallery, threshold: f64) -> Vec<ChildReview> {
+    gallery
+        .feature_sets
+        .iter()
+        .filter_map(|fs| {
+            let mut flagged: Vec<FlaggedPhoto> = fs
+                .atomics
+                .iter()
+                .map(|atomic| FlaggedPhoto {
+                    photo_file_name: atomic.photo_file_name.clone(),
+                    distance_to_average: euclidean_distance(
+                        &atomic.feature_vector,
+                        &fs.average.feature_vector,
+                    ),
+                    distance_to_median: euclidean_distance(
+                        &atomic.feature_vector,
+                        &fs.median.feature_vector,
+                    ),
+                })
+                .filter(|p| p.distance_to_average > threshold || p.distance_to_median > threshold)
+                .collect();
+            if flagged.is_empty() {
+                return None;
+            }
+            flagged.sort_by(|a, b| b.distance_to_average.total_cmp(&a.distance_to_average));
+
+            Some(ChildReview {
+                child_id: fs.average.child_id.clone(),
+                medoid_photo: fs.medoid.photo_file_name.clone(),
+                flagged,
+            })
+        })
+        .collect()
+}
+
+/// Renders the review as a single HTML page with the face thumbnails embedded,
+/// so it can be mailed or opened without access to the photo folder.
+pub fn render_review_html(
+    reviews: &[ChildReview],
+    photo_dir: &str,
+    detectors: &Detectors,
+    threshold: f64,
+) -> String {
+    const THUMB_SIZE: u32 = 96;
+    let names: HashSet<String> = reviews
+        .iter()
+        .flat_map(|r| {
+            std::iter::once(r.medoid_photo.clone())
+                .chain(r.flagged.iter().map(|p| p.photo_file_name.clone()))
+        })
+        .collect();
+    let thumbnails = face_thumbnails(photo_dir, &names, detectors, THUMB_SIZE);
+    let thumbnail = |name: &str| thumbnail_html(thumbnails.get(name));
+    let num_flagged: usize = reviews.iter().map(|r| r.flagged.len()).sum();
+
+    let mut html = String::new();
+    html.push_str(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Face review</title>\n<style>\n\
+         body { font-family: sans-serif; margin: 2em; }\n\
+         .child { border-top: 1px solid #ccc; padding: 1em 0; }\n\
+         .photos { display: flex; flex-wrap: wrap; gap: 12px; }\n\
+         figure { margin: 0; width: 110px; font-size: 12px; word-break: break-all; }\n\
+         figure img, .missing { width: 96px; height: 96px; display: block; background: #eee; }\n\
+         .missing { line-height: 96px; text-align: center; color: #888; }\n\
+         .medoid img, .medoid .missing { border: 3px solid #2a2; }\n\
+         .flagged img, .flagged .missing { border: 3px solid #d22; }\n\
+         textarea { width: 100%; height: 12em; }\n\
+         </style>\n</head>\n<body>\n",
+    );
+    let _ = writeln!(
+        html,
+        "<h1>Face review</h1>\n<p>{} photos across {} children are further than {:.2} from their child's average or median.</p>",
+        num_flagged,
+        reviews.len(),
+        threshold
+    );
+
+    let files: Vec<&str> = reviews
+        .iter()
+        .flat_map(|r| r.flagged.iter().map(|p| p.photo_file_name.as_str()))
+        .collect();
+    let _ = writeln!(
+        html,
+        "<h2>Files to fix</h2>\n<textarea id=\"files\" readonly>{}</textarea>\n<button onclick=\"navigator.clipboard.writeText(document.getElementById('files').value)\">Copy list</button>",
+        escape_xml(&files.join("\n"))
+    );
+
+    for review in reviews {
+        let _ = writeln!(
+            html,
+            "<div class=\"child\">\n<h2>{}</h2>\n<div class=\"photos\">",
+            escape_xml(&review.child_id)
+        );
+        let _ = writeln!(
+            html,
+            "<figure class=\"medoid\">{}<figcaption>Medoid<br>{}</figcaption></figure>",
+            thumbnail(&review.medoid_photo),
+            escape_xml(&review.medoid_photo)
+        );
+        for photo in &review.flagged {
+            let _ = writeln!(
+                html,
+                "<figure class=\"flagged\">{}<figcaption>{}<br>AVG {:.3}<br>MED {:.3}</figcaption></figure>",
+                thumbnail(&photo.photo_file_name),
+                escape_xml(&photo.photo_file_name),
+                photo.distance_to_average,
+                photo.distance_to_median
+            );
+        }
+        html.push_str("</div>\n</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+// Face thumbnail as an inline PNG, or a placeholder when the photo can't be found or read
+fn thumbnail_html(thumbnail: Option<&RgbImage>) -> String {
+    const PLACEHOLDER: &str = "<div class=\"missing\">No photo</div>";
+    let Some(thumbnail) = thumbnail else {
+        return PLACEHOLDER.to_string();
+    };
+
+    let mut png = Cursor::new(Vec::new());
+    if thumbnail
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .is_err()
+    {
+        return PLACEHOLDER.to_string();
+    }
+    format!(
+        "<img src=\"data:image/png;base64,{}\">",
+        STANDARD.encode(png.into_inner())
+    )
+}
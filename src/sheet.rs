@@ -0,0 +1,129 @@
+use crate::chip::face_thumbnails;
+use crate::compare::{euclidean_distance, FeatureSet, Reference};
+use crate::detect::Detectors;
+use crate::font::GLYPH_WIDTH;
+use crate::tool::{draw_rectangle_thick, draw_text, text_size};
+use dlib_face_recognition::Rectangle;
+use image::{imageops, Rgb, RgbImage};
+use std::collections::HashSet;
+use std::os::raw::c_long;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SheetConfig {
+    pub thumb_size: u32,
+    pub columns: u32,
+    // Atomics further than this from the reference get a red border
+    pub threshold: f64,
+    pub reference: Reference,
+}
+
+impl Default for SheetConfig {
+    fn default() -> Self {
+        SheetConfig {
+            thumb_size: 120,
+            columns: 6,
+            threshold: 0.45,
+            reference: Reference::Average,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SheetEntry {
+    pub photo_file_name: String,
+    pub distance: f64,
+    pub outlier: bool,
+}
+
+// A child's atomics, furthest from the reference first
+pub fn contact_sheet_entries(feature_set: &FeatureSet, config: &SheetConfig) -> Vec<SheetEntry> {
+    let reference = &feature_set.reference(config.reference).feature_vector;
+    let mut entries: Vec<SheetEntry> = feature_set
+        .atomics
+        .iter()
+        .map(|atomic| {
+            let distance = euclidean_distance(&atomic.feature_vector, reference);
+            SheetEntry {
+                photo_file_name: atomic.photo_file_name.clone(),
+                distance,
+                outlier: distance > config.threshold,
+            }
+        })
+        .collect();
+    entries.sort_by(|a, b| b.distance.total_cmp(&a.distance));
+    entries
+}
+
+/// Lays the entries out in a grid of face thumbnails, each captioned with its
+/// distance and file name. `photo_dir` may also be an archive. Photos missing
+/// from it or that fail to load are shown as grey tiles.
+pub fn render_contact_sheet(
+    entries: &[SheetEntry],
+    photo_dir: &str,
+    detectors: &Detectors,
+    config: &SheetConfig,
+) -> RgbImage {
+    const GAP: u32 = 6;
+    let black = Rgb([0, 0, 0]);
+    let red = Rgb([255, 0, 0]);
+    let grey = Rgb([128, 128, 128]);
+
+    let thumb = config.thumb_size.max(1);
+    let names: HashSet<String> = entries.iter().map(|e| e.photo_file_name.clone()).collect();
+    let thumbnails = face_thumbnails(photo_dir, &names, detectors, thumb);
+    let columns = config.columns.max(1);
+    let rows = (entries.len() as u32).div_ceil(columns);
+    let (_, line_height) = text_size("0", 1);
+    let cell_width = thumb + GAP;
+    let cell_height = thumb + 2 * (line_height + 2) + GAP;
+    // Caption characters that fit under a thumbnail
+    let max_chars = (thumb / (GLYPH_WIDTH + 1)) as usize;
+
+    let mut sheet = RgbImage::from_pixel(
+        columns * cell_width + GAP,
+        rows.max(1) * cell_height + GAP,
+        Rgb([255, 255, 255]),
+    );
+
+    for (i, entry) in entries.iter().enumerate() {
+        let x = GAP + (i as u32 % columns) * cell_width;
+        let y = GAP + (i as u32 / columns) * cell_height;
+
+        let thumbnail = thumbnails
+            .get(&entry.photo_file_name)
+            .cloned()
+            .unwrap_or_else(|| RgbImage::from_pixel(thumb, thumb, grey));
+        imageops::replace(&mut sheet, &thumbnail, x as i64, y as i64);
+
+        if entry.outlier {
+            let rect = Rectangle {
+                left: x as c_long,
+                top: y as c_long,
+                right: (x + thumb - 1) as c_long,
+                bottom: (y + thumb - 1) as c_long,
+            };
+            draw_rectangle_thick(&mut sheet, &rect, red, 3);
+        }
+
+        let caption_y = (y + thumb + 2) as c_long;
+        let colour = if entry.outlier { red } else { black };
+        draw_text(
+            &mut sheet,
+            x as c_long,
+            caption_y,
+            &format!("{:.3}", entry.distance),
+            colour,
+            1,
+        );
+        let name: String = entry.photo_file_name.chars().take(max_chars).collect();
+        draw_text(
+            &mut sheet,
+            x as c_long,
+            caption_y + (line_height + 2) as c_long,
+            &name,
+            black,
+            1,
+        );
+    }
+    sheet
+}
@@ -1,6 +1,28 @@
-pub fn compute_average(features: &[Vec<f64>]) -> Vec<f64> {
+use crate::compare::euclidean_distance;
+use crate::error::StatsError;
+use serde::{Deserialize, Serialize};
+
+// Checks the input is non-empty, finite and of one dimension; returns that dimension
+fn validate(features: &[Vec<f64>]) -> Result<usize, StatsError> {
+    let dim = features.first().ok_or(StatsError::Empty)?.len();
+
+    for feature in features {
+        if feature.len() != dim {
+            return Err(StatsError::DimensionMismatch {
+                expected: dim,
+                found: feature.len(),
+            });
+        }
+        if feature.iter().any(|val| !val.is_finite()) {
+            return Err(StatsError::NonFinite);
+        }
+    }
+    Ok(dim)
+}
+
+pub fn compute_average(features: &[Vec<f64>]) -> Result<Vec<f64>, StatsError> {
     let len = features.len();
-    let mut sum = vec![0.0; features[0].len()];
+    let mut sum = vec![0.0; validate(features)?];
 
     for feature in features {
         for (i, val) in feature.iter().enumerate() {
@@ -8,12 +30,32 @@ pub fn compute_average(features: &[Vec<f64>]) -> Vec<f64> {
         }
     }
 
-    sum.iter().map(|&x| x / len as f64).collect()
+    Ok(sum.iter().map(|&x| x / len as f64).collect())
 }
 
-pub fn compute_median(features: &[Vec<f64>]) -> Vec<f64> {
+// Per-dimension mean where each feature contributes in proportion to its weight
+pub fn compute_weighted_average(
+    features: &[Vec<f64>],
+    weights: &[f64],
+) -> Result<Vec<f64>, StatsError> {
+    let mut sum = vec![0.0; validate(features)?];
+    let total: f64 = weights.iter().sum();
+    if weights.len() != features.len() || !total.is_finite() || total <= 0.0 {
+        return Err(StatsError::InvalidWeights);
+    }
+
+    for (feature, weight) in features.iter().zip(weights.iter()) {
+        for (i, val) in feature.iter().enumerate() {
+            sum[i] += val * weight;
+        }
+    }
+
+    Ok(sum.iter().map(|&x| x / total).collect())
+}
+
+pub fn compute_median(features: &[Vec<f64>]) -> Result<Vec<f64>, StatsError> {
     let len = features.len();
-    let mut median = vec![vec![]; features[0].len()];
+    let mut median = vec![vec![]; validate(features)?];
 
     for feature in features {
         for (i, val) in feature.iter().enumerate() {
@@ -21,15 +63,596 @@ pub fn compute_median(features: &[Vec<f64>]) -> Vec<f64> {
         }
     }
 
-    median
+    Ok(median
         .iter_mut()
         .map(|vals| {
-            vals.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            vals.sort_by(|a, b| a.total_cmp(b));
             if len % 2 == 0 {
                 (vals[len / 2 - 1] + vals[len / 2]) / 2.0
             } else {
                 vals[len / 2]
             }
         })
+        .collect())
+}
+
+// Index of the feature with the smallest total distance to all the others
+pub fn compute_medoid(features: &[Vec<f64>]) -> Result<usize, StatsError> {
+    validate(features)?;
+    let len = features.len();
+    let mut totals = vec![0.0; len];
+
+    for i in 0..len {
+        for j in (i + 1)..len {
+            let distance = euclidean_distance(&features[i], &features[j]);
+            totals[i] += distance;
+            totals[j] += distance;
+        }
+    }
+
+    Ok(totals
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i)
+        .unwrap_or(0))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RobustConfig {
+    pub max_iterations: usize,
+    // Iteration stops once the centroid moves less than this
+    pub tolerance: f64,
+    // Features farther than this from the centroid are left out of the next mean
+    pub outlier_threshold: f64,
+}
+
+impl Default for RobustConfig {
+    fn default() -> Self {
+        RobustConfig {
+            max_iterations: 50,
+            tolerance: 1e-6,
+            outlier_threshold: 0.45,
+        }
+    }
+}
+
+// Geometric median via Weiszfeld's algorithm, starting from the mean
+pub fn compute_geometric_median(
+    features: &[Vec<f64>],
+    config: &RobustConfig,
+) -> Result<Vec<f64>, StatsError> {
+    let mut centroid = compute_average(features)?;
+
+    for _ in 0..config.max_iterations {
+        let mut weighted_sum = vec![0.0; centroid.len()];
+        let mut weight_total = 0.0;
+
+        for feature in features {
+            let distance = euclidean_distance(feature, &centroid);
+            // A feature sitting on the centroid would get infinite weight
+            if distance < 1e-12 {
+                continue;
+            }
+            let weight = 1.0 / distance;
+            for (i, val) in feature.iter().enumerate() {
+                weighted_sum[i] += val * weight;
+            }
+            weight_total += weight;
+        }
+        if weight_total == 0.0 {
+            break;
+        }
+
+        let next: Vec<f64> = weighted_sum.iter().map(|x| x / weight_total).collect();
+        let shift = euclidean_distance(&next, &centroid);
+        centroid = next;
+        if shift < config.tolerance {
+            break;
+        }
+    }
+
+    Ok(centroid)
+}
+
+/// Mean of the inliers only. Starts at the geometric median, then repeatedly
+/// drops features beyond `outlier_threshold` and re-averages the rest until the
+/// centroid settles. Falls back to the geometric median when nothing is close.
+pub fn compute_robust_average(
+    features: &[Vec<f64>],
+    config: &RobustConfig,
+) -> Result<Vec<f64>, StatsError> {
+    let mut centroid = compute_geometric_median(features, config)?;
+
+    for _ in 0..config.max_iterations {
+        let inliers: Vec<Vec<f64>> = features
+            .iter()
+            .filter(|f| euclidean_distance(f, &centroid) <= config.outlier_threshold)
+            .cloned()
+            .collect();
+        if inliers.is_empty() {
+            break;
+        }
+
+        let next = compute_average(&inliers)?;
+        let shift = euclidean_distance(&next, &centroid);
+        centroid = next;
+        if shift < config.tolerance {
+            break;
+        }
+    }
+
+    Ok(centroid)
+}
+
+/// K-means over the features, returning up to `k` prototype vectors. Centres are
+/// seeded deterministically: the medoid first, then repeatedly the densest-area
+/// feature farthest from every centre chosen so far, so an isolated (often
+/// mislabeled) photo is never a seed. Clusters smaller than `max(2, n / 10)` are
+/// dropped after convergence; if none survive, the robust average is returned.
+pub fn compute_prototypes(
+    features: &[Vec<f64>],
+    k: usize,
+    max_iterations: usize,
+) -> Result<Vec<Vec<f64>>, StatsError> {
+    if k == 0 {
+        return Ok(Vec::new());
+    }
+    validate(features)?;
+    let min_size = (features.len() / 10).max(2);
+    let k = k.min(features.len() / min_size).max(1);
+
+    let candidates = dense_features(features, min_size);
+    let mut centres = vec![features[compute_medoid(features)?].clone()];
+    while centres.len() < k {
+        let farthest = candidates
+            .iter()
+            .map(|&i| &features[i])
+            .max_by(|a, b| {
+                nearest_centre(a, &centres)
+                    .1
+                    .total_cmp(&nearest_centre(b, &centres).1)
+            })
+            .cloned()
+            .unwrap_or_else(|| features[0].clone());
+        centres.push(farthest);
+    }
+
+    centres = run_kmeans(features, centres, max_iterations)?;
+    // Drop clusters too small to be a real look of the child. The survivors are kept
+    // as they are: re-running k-means would let them drift back onto the outliers.
+    let sizes = cluster_sizes(features, &centres);
+    let centres: Vec<Vec<f64>> = centres
+        .into_iter()
+        .zip(sizes)
+        .filter(|(_, size)| *size >= min_size)
+        .map(|(centre, _)| centre)
+        .collect();
+    if centres.is_empty() {
+        return Ok(vec![compute_robust_average(
+            features,
+            &RobustConfig::default(),
+        )?]);
+    }
+    Ok(centres)
+}
+
+// Features at least as tightly surrounded as the median one, judged by the
+// distance to their `neighbours`-th nearest other feature
+fn dense_features(features: &[Vec<f64>], neighbours: usize) -> Vec<usize> {
+    if features.len() <= neighbours {
+        return (0..features.len()).collect();
+    }
+    let radii: Vec<f64> = features
+        .iter()
+        .map(|f| {
+            let mut distances: Vec<f64> = features
+                .iter()
+                .map(|other| euclidean_distance(f, other))
+                .collect();
+            distances.sort_by(|a, b| a.total_cmp(b));
+            // distances[0] is the feature itself
+            distances[neighbours]
+        })
+        .collect();
+    let mut sorted = radii.clone();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let median = sorted[sorted.len() / 2];
+
+    (0..features.len())
+        .filter(|&i| radii[i] <= median)
         .collect()
 }
+
+fn run_kmeans(
+    features: &[Vec<f64>],
+    mut centres: Vec<Vec<f64>>,
+    max_iterations: usize,
+) -> Result<Vec<Vec<f64>>, StatsError> {
+    let mut assignments = vec![usize::MAX; features.len()];
+    for _ in 0..max_iterations {
+        let next: Vec<usize> = features
+            .iter()
+            .map(|f| nearest_centre(f, &centres).0)
+            .collect();
+        if next == assignments {
+            break;
+        }
+        assignments = next;
+
+        for (c, centre) in centres.iter_mut().enumerate() {
+            let members: Vec<Vec<f64>> = features
+                .iter()
+                .zip(assignments.iter())
+                .filter(|(_, &a)| a == c)
+                .map(|(f, _)| f.clone())
+                .collect();
+            // An empty cluster keeps its previous centre
+            if !members.is_empty() {
+                *centre = compute_average(&members)?;
+            }
+        }
+    }
+    Ok(centres)
+}
+
+fn cluster_sizes(features: &[Vec<f64>], centres: &[Vec<f64>]) -> Vec<usize> {
+    let mut sizes = vec![0; centres.len()];
+    for feature in features {
+        sizes[nearest_centre(feature, centres).0] += 1;
+    }
+    sizes
+}
+
+// Index of and distance to the closest centre
+pub fn nearest_centre(feature: &[f64], centres: &[Vec<f64>]) -> (usize, f64) {
+    centres
+        .iter()
+        .map(|c| euclidean_distance(feature, c))
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, f64::INFINITY))
+}
+
+// Per-dimension population variance
+pub fn compute_variance(features: &[Vec<f64>]) -> Result<Vec<f64>, StatsError> {
+    let mean = compute_average(features)?;
+    let len = features.len() as f64;
+    let mut variance = vec![0.0; mean.len()];
+
+    for feature in features {
+        for (i, val) in feature.iter().enumerate() {
+            variance[i] += (val - mean[i]).powi(2);
+        }
+    }
+
+    Ok(variance.iter().map(|&x| x / len).collect())
+}
+
+pub fn compute_std(features: &[Vec<f64>]) -> Result<Vec<f64>, StatsError> {
+    Ok(compute_variance(features)?
+        .iter()
+        .map(|x| x.sqrt())
+        .collect())
+}
+
+// Population covariance matrix, dimension x dimension
+pub fn compute_covariance(features: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, StatsError> {
+    let mean = compute_average(features)?;
+    let len = features.len() as f64;
+    let dim = mean.len();
+    let mut covariance = vec![vec![0.0; dim]; dim];
+
+    for feature in features {
+        let centred: Vec<f64> = feature
+            .iter()
+            .zip(mean.iter())
+            .map(|(v, m)| v - m)
+            .collect();
+        for (row, ci) in covariance.iter_mut().zip(centred.iter()) {
+            for (cell, cj) in row.iter_mut().zip(centred.iter()) {
+                *cell += ci * cj;
+            }
+        }
+    }
+    for cell in covariance.iter_mut().flatten() {
+        *cell /= len;
+    }
+
+    Ok(covariance)
+}
+
+// Mean distance over all unordered pairs; 0 for a single feature
+pub fn mean_pairwise_distance(features: &[Vec<f64>]) -> Result<f64, StatsError> {
+    validate(features)?;
+    let len = features.len();
+    if len < 2 {
+        return Ok(0.0);
+    }
+
+    let mut total = 0.0;
+    for i in 0..len {
+        for j in (i + 1)..len {
+            total += euclidean_distance(&features[i], &features[j]);
+        }
+    }
+
+    Ok(total / (len * (len - 1) / 2) as f64)
+}
+
+/// How widely a set of encodings is scattered, for data-quality reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct Spread {
+    pub count: usize,
+    pub mean_distance_to_centroid: f64,
+    pub max_distance_to_centroid: f64,
+    pub mean_pairwise_distance: f64,
+    // Sum of the per-dimension variances (trace of the covariance matrix)
+    pub total_variance: f64,
+}
+
+pub fn compute_spread(features: &[Vec<f64>]) -> Result<Spread, StatsError> {
+    let centroid = compute_average(features)?;
+    let distances: Vec<f64> = features
+        .iter()
+        .map(|f| euclidean_distance(f, &centroid))
+        .collect();
+
+    Ok(Spread {
+        count: features.len(),
+        mean_distance_to_centroid: distances.iter().sum::<f64>() / features.len() as f64,
+        max_distance_to_centroid: distances.iter().cloned().fold(0.0, f64::max),
+        mean_pairwise_distance: mean_pairwise_distance(features)?,
+        total_variance: compute_variance(features)?.iter().sum(),
+    })
+}
+
+/// Streaming per-child aggregates, so one encoding can be enrolled or removed
+/// in O(dim) without re-reading the other atomics. Mean and variance follow
+/// Welford's algorithm and are exact; the median is a stochastic approximation
+/// that nudges each dimension towards new values by a step scaled to its spread.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OnlineStats {
+    pub count: usize,
+    pub mean: Vec<f64>,
+    // Sum of squared deviations from the mean, per dimension
+    m2: Vec<f64>,
+    pub median: Vec<f64>,
+}
+
+impl OnlineStats {
+    // Seeds the statistics exactly from a full set of features
+    pub fn from_features(features: &[Vec<f64>]) -> Result<Self, StatsError> {
+        let mean = compute_average(features)?;
+        let variance = compute_variance(features)?;
+
+        Ok(OnlineStats {
+            count: features.len(),
+            m2: variance.iter().map(|v| v * features.len() as f64).collect(),
+            median: compute_median(features)?,
+            mean,
+        })
+    }
+    pub fn variance(&self) -> Vec<f64> {
+        if self.count == 0 {
+            return vec![0.0; self.m2.len()];
+        }
+        self.m2.iter().map(|m| m / self.count as f64).collect()
+    }
+    pub fn add(&mut self, feature: &[f64]) -> Result<(), StatsError> {
+        self.check(feature)?;
+        if self.count == 0 {
+            *self = OnlineStats {
+                count: 1,
+                mean: feature.to_vec(),
+                m2: vec![0.0; feature.len()],
+                median: feature.to_vec(),
+            };
+            return Ok(());
+        }
+
+        self.count += 1;
+        let step = self.median_steps();
+        for (i, val) in feature.iter().enumerate() {
+            let delta = val - self.mean[i];
+            self.mean[i] += delta / self.count as f64;
+            self.m2[i] += delta * (val - self.mean[i]);
+            self.median[i] += step[i] * (val - self.median[i]).signum();
+        }
+        Ok(())
+    }
+    pub fn remove(&mut self, feature: &[f64]) -> Result<(), StatsError> {
+        self.check(feature)?;
+        if self.count <= 1 {
+            *self = OnlineStats {
+                count: 0,
+                mean: vec![0.0; feature.len()],
+                m2: vec![0.0; feature.len()],
+                median: vec![0.0; feature.len()],
+            };
+            return Ok(());
+        }
+
+        let step = self.median_steps();
+        self.count -= 1;
+        for (i, val) in feature.iter().enumerate() {
+            let previous_mean = self.mean[i];
+            self.mean[i] = (previous_mean * (self.count + 1) as f64 - val) / self.count as f64;
+            self.m2[i] = (self.m2[i] - (val - self.mean[i]) * (val - previous_mean)).max(0.0);
+            self.median[i] -= step[i] * (val - self.median[i]).signum();
+        }
+        Ok(())
+    }
+    // Step sizes shrink as more samples arrive, scaled by each dimension's spread
+    fn median_steps(&self) -> Vec<f64> {
+        self.variance()
+            .iter()
+            .map(|v| 1.5 * v.sqrt() / self.count as f64)
+            .collect()
+    }
+    fn check(&self, feature: &[f64]) -> Result<(), StatsError> {
+        if self.count > 0 && feature.len() != self.mean.len() {
+            return Err(StatsError::DimensionMismatch {
+                expected: self.mean.len(),
+                found: feature.len(),
+            });
+        }
+        if feature.iter().any(|val| !val.is_finite()) {
+            return Err(StatsError::NonFinite);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len());
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < 1e-9, "{:?} != {:?}", a, b);
+        }
+    }
+
+    // Nine photos close to the origin and one mislabeled photo at (1, 0)
+    fn child_with_outlier() -> Vec<Vec<f64>> {
+        let mut features: Vec<Vec<f64>> = (0..9)
+            .map(|i| vec![0.01 * i as f64, 0.02 * (i % 3) as f64])
+            .collect();
+        features.push(vec![1.0, 0.0]);
+        features
+    }
+
+    #[test]
+    fn empty_input_is_rejected() {
+        assert_eq!(compute_average(&[]), Err(StatsError::Empty));
+        assert_eq!(compute_median(&[]), Err(StatsError::Empty));
+        assert_eq!(compute_medoid(&[]), Err(StatsError::Empty));
+        assert_eq!(
+            compute_robust_average(&[], &RobustConfig::default()),
+            Err(StatsError::Empty)
+        );
+        assert_eq!(compute_prototypes(&[], 3, 100), Err(StatsError::Empty));
+        assert!(OnlineStats::from_features(&[]).is_err());
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected() {
+        let features = vec![vec![0.0, 1.0], vec![f64::NAN, 1.0]];
+        assert_eq!(compute_average(&features), Err(StatsError::NonFinite));
+        assert_eq!(compute_median(&features), Err(StatsError::NonFinite));
+        let features = vec![vec![0.0, f64::INFINITY]];
+        assert_eq!(compute_medoid(&features), Err(StatsError::NonFinite));
+    }
+
+    #[test]
+    fn dimension_mismatch_is_rejected() {
+        let features = vec![vec![0.0, 1.0], vec![1.0]];
+        let expected = Err(StatsError::DimensionMismatch {
+            expected: 2,
+            found: 1,
+        });
+        assert_eq!(compute_average(&features), expected);
+        assert_eq!(compute_median(&features), expected);
+    }
+
+    #[test]
+    fn invalid_weights_are_rejected() {
+        let features = vec![vec![0.0], vec![1.0]];
+        assert_eq!(
+            compute_weighted_average(&features, &[1.0]),
+            Err(StatsError::InvalidWeights)
+        );
+        assert_eq!(
+            compute_weighted_average(&features, &[0.0, 0.0]),
+            Err(StatsError::InvalidWeights)
+        );
+        assert_close(
+            &compute_weighted_average(&features, &[1.0, 3.0]).unwrap(),
+            &[0.75],
+        );
+    }
+
+    #[test]
+    fn prototypes_leave_out_a_mislabeled_photo() {
+        let features = child_with_outlier();
+        let prototypes = compute_prototypes(&features, 3, 100).unwrap();
+
+        assert!(!prototypes.is_empty());
+        for prototype in &prototypes {
+            assert!(euclidean_distance(prototype, &[1.0, 0.0]) > 0.6);
+        }
+        assert!(nearest_centre(&[1.0, 0.0], &prototypes).1 > 0.6);
+    }
+
+    #[test]
+    fn prototypes_cover_separate_looks() {
+        let mut features: Vec<Vec<f64>> = (0..6).map(|i| vec![0.01 * i as f64, 0.0]).collect();
+        features.extend((0..6).map(|i| vec![0.5 + 0.01 * i as f64, 0.0]));
+        let prototypes = compute_prototypes(&features, 2, 100).unwrap();
+
+        assert_eq!(prototypes.len(), 2);
+        assert!(nearest_centre(&[0.025, 0.0], &prototypes).1 < 1e-9);
+        assert!(nearest_centre(&[0.525, 0.0], &prototypes).1 < 1e-9);
+    }
+
+    #[test]
+    fn prototypes_fall_back_to_robust_average() {
+        let prototypes = compute_prototypes(&[vec![0.3, 0.4]], 3, 100).unwrap();
+        assert_eq!(prototypes, vec![vec![0.3, 0.4]]);
+        assert!(compute_prototypes(&[vec![0.3, 0.4]], 0, 100)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn online_stats_add_matches_batch() {
+        let features = child_with_outlier();
+        let mut online = OnlineStats::from_features(&features[..5]).unwrap();
+        for feature in &features[5..] {
+            online.add(feature).unwrap();
+        }
+
+        let batch = OnlineStats::from_features(&features).unwrap();
+        assert_eq!(online.count, features.len());
+        assert_close(&online.mean, &batch.mean);
+        assert_close(&online.variance(), &batch.variance());
+    }
+
+    #[test]
+    fn online_stats_remove_undoes_add() {
+        let features = child_with_outlier();
+        let seeded = OnlineStats::from_features(&features[..9]).unwrap();
+        let mut online = seeded.clone();
+        online.add(&features[9]).unwrap();
+        online.remove(&features[9]).unwrap();
+
+        assert_eq!(online.count, seeded.count);
+        assert_close(&online.mean, &seeded.mean);
+        assert_close(&online.variance(), &seeded.variance());
+    }
+
+    #[test]
+    fn online_stats_remove_last_empties() {
+        let mut online = OnlineStats::from_features(&[vec![0.2, 0.4]]).unwrap();
+        online.remove(&[0.2, 0.4]).unwrap();
+        assert_eq!(online.count, 0);
+        online.add(&[0.6, 0.8]).unwrap();
+        assert_close(&online.mean, &[0.6, 0.8]);
+    }
+
+    #[test]
+    fn online_stats_reject_bad_features() {
+        let mut online = OnlineStats::from_features(&[vec![0.2, 0.4]]).unwrap();
+        assert_eq!(
+            online.add(&[0.1]),
+            Err(StatsError::DimensionMismatch {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(online.add(&[f64::NAN, 0.0]), Err(StatsError::NonFinite));
+        assert_eq!(online.count, 1);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::compare::Gallery;
+use crate::feature::encode_all_faces;
+use crate::tool::{draw_label, draw_rectangle_thick, stroke_scale};
+use dlib_face_recognition::*;
+use image::{Rgb, RgbImage};
+
+#[derive(Debug, Clone)]
+pub struct FaceTag {
+    pub rect: Rectangle,
+    // None when no enrolled child is within the threshold
+    pub child_id: Option<String>,
+    // Distance to the nearest enrolled child, None when the gallery is empty
+    pub distance: Option<f64>,
+}
+
+impl FaceTag {
+    pub fn label(&self) -> String {
+        let name = self.child_id.as_deref().unwrap_or("unknown");
+        match self.distance {
+            Some(distance) => format!("{} {:.2}", name, distance),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Identifies every face in a photo against the enrolled gallery.
+pub struct Tagger {
+    gallery: Gallery,
+    threshold: f64,
+    face_detector: FaceDetectorCnn,
+    landmark_predictor: LandmarkPredictor,
+    face_encoder: FaceEncoderNetwork,
+}
+
+impl Tagger {
+    pub fn new(db_path: &str, threshold: f64) -> Result<Self, String> {
+        Ok(Tagger {
+            gallery: Gallery::from_db_table(db_path)?,
+            threshold,
+            face_detector: FaceDetectorCnn::default()?,
+            landmark_predictor: LandmarkPredictor::default()?,
+            face_encoder: FaceEncoderNetwork::default()?,
+        })
+    }
+    pub fn tag(&self, photo_path: &str) -> Result<Vec<FaceTag>, String> {
+        let image_buffer = image::open(photo_path)
+            .map_err(|e| format!("Error opening image {}: {}", photo_path, e))?
+            .to_rgb8();
+        Ok(self.tag_image(&image_buffer))
+    }
+    pub fn tag_image(&self, image: &RgbImage) -> Vec<FaceTag> {
+        let image_matrix = ImageMatrix::from_image(image);
+
+        encode_all_faces(
+            &image_matrix,
+            &self.face_detector,
+            &self.landmark_predictor,
+            &self.face_encoder,
+        )
+        .into_iter()
+        .map(|(rect, feature_vector)| {
+            let nearest = self.gallery.nearest(&feature_vector);
+            FaceTag {
+                rect,
+                child_id: nearest
+                    .filter(|(_, distance)| *distance <= self.threshold)
+                    .map(|(fs, _)| fs.average.child_id.clone()),
+                distance: nearest.map(|(_, distance)| distance),
+            }
+        })
+        .collect()
+    }
+}
+
+// Boxes identified faces in green and unknown faces in red, labelled above the box
+pub fn render_tags(image: &mut RgbImage, tags: &[FaceTag]) {
+    let red = Rgb([255, 0, 0]);
+    let green = Rgb([0, 255, 0]);
+    let scale = stroke_scale(image);
+
+    for tag in tags {
+        let colour = if tag.child_id.is_some() { green } else { red };
+        draw_rectangle_thick(image, &tag.rect, colour, scale);
+        draw_label(image, &tag.rect, &tag.label(), colour, scale);
+    }
+}
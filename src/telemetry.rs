@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use tracing::span::{Attributes, Id};
+use tracing::{info, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Human,
+    Json,
+}
+
+impl LogFormat {
+    // FACE_REC_LOG_FORMAT=json switches to one JSON object per line
+    pub fn from_env() -> Self {
+        match std::env::var("FACE_REC_LOG_FORMAT") {
+            Ok(format) if format.eq_ignore_ascii_case("json") => LogFormat::Json,
+            _ => LogFormat::Human,
+        }
+    }
+}
+
+// Upper bounds of the duration histogram buckets; the last bucket is open-ended
+const BUCKET_BOUNDS_MS: [f64; 4] = [1.0, 10.0, 100.0, 1000.0];
+const BUCKET_LABELS: [&str; 5] = ["<1ms", "<10ms", "<100ms", "<1s", ">=1s"];
+
+#[derive(Debug, Clone)]
+pub struct StageSummary {
+    pub stage: &'static str,
+    pub count: usize,
+    pub total_ms: f64,
+    pub mean_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    pub histogram: [usize; 5],
+}
+
+/// Durations of every closed span, grouped by span name.
+#[derive(Default)]
+pub struct StageTimings {
+    durations: Mutex<HashMap<&'static str, Vec<f64>>>,
+}
+
+impl StageTimings {
+    fn record(&self, stage: &'static str, elapsed_ms: f64) {
+        if let Ok(mut durations) = self.durations.lock() {
+            durations.entry(stage).or_default().push(elapsed_ms);
+        }
+    }
+    // Clears the timings so the next summary covers a single run
+    pub fn reset(&self) {
+        if let Ok(mut durations) = self.durations.lock() {
+            durations.clear();
+        }
+    }
+    // Per-stage statistics, most total time first
+    pub fn summary(&self) -> Vec<StageSummary> {
+        let Ok(durations) = self.durations.lock() else {
+            return Vec::new();
+        };
+        let mut summaries: Vec<StageSummary> = durations
+            .iter()
+            .filter(|(_, samples)| !samples.is_empty())
+            .map(|(stage, samples)| {
+                let mut sorted = samples.clone();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                let total_ms: f64 = sorted.iter().sum();
+                let mut histogram = [0; 5];
+                for sample in &sorted {
+                    let bucket = BUCKET_BOUNDS_MS
+                        .iter()
+                        .position(|bound| sample < bound)
+                        .unwrap_or(BUCKET_BOUNDS_MS.len());
+                    histogram[bucket] += 1;
+                }
+
+                StageSummary {
+                    stage,
+                    count: sorted.len(),
+                    total_ms,
+                    mean_ms: total_ms / sorted.len() as f64,
+                    p95_ms: sorted[((sorted.len() - 1) as f64 * 0.95).round() as usize],
+                    max_ms: sorted[sorted.len() - 1],
+                    histogram,
+                }
+            })
+            .collect();
+        summaries.sort_by(|a, b| b.total_ms.total_cmp(&a.total_ms));
+        summaries
+    }
+    pub fn format_summary(&self) -> String {
+        let mut text = String::new();
+        let _ = write!(
+            text,
+            "{:<16}{:>8}{:>12}{:>10}{:>10}{:>10}",
+            "Stage", "count", "total ms", "mean ms", "p95 ms", "max ms"
+        );
+        for label in BUCKET_LABELS {
+            let _ = write!(text, "{:>8}", label);
+        }
+        text.push('\n');
+
+        for s in self.summary() {
+            let _ = write!(
+                text,
+                "{:<16}{:>8}{:>12.1}{:>10.1}{:>10.1}{:>10.1}",
+                s.stage, s.count, s.total_ms, s.mean_ms, s.p95_ms, s.max_ms
+            );
+            for count in s.histogram {
+                let _ = write!(text, "{:>8}", count);
+            }
+            text.push('\n');
+        }
+        text
+    }
+}
+
+pub fn stage_timings() -> &'static StageTimings {
+    static TIMINGS: OnceLock<StageTimings> = OnceLock::new();
+    TIMINGS.get_or_init(StageTimings::default)
+}
+
+struct SpanStart(Instant);
+
+// Times every span regardless of the log level, feeding `stage_timings`
+struct TimingLayer;
+
+impl<S> Layer<S> for TimingLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(&id) {
+            if let Some(SpanStart(start)) = span.extensions().get::<SpanStart>() {
+                stage_timings().record(
+                    span.metadata().name(),
+                    start.elapsed().as_secs_f64() * 1000.0,
+                );
+            }
+        }
+    }
+}
+
+/// Installs the global logger. Levels come from RUST_LOG (default `info`);
+/// logs go to stderr so they don't mix with menu output. Calling this more
+/// than once has no effect.
+pub fn init_logging(format: LogFormat) {
+    let filter = || EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let output = match format {
+        LogFormat::Human => fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_filter(filter())
+            .boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_writer(std::io::stderr)
+            .with_filter(filter())
+            .boxed(),
+    };
+
+    let _ = tracing_subscriber::registry()
+        .with(output)
+        .with(TimingLayer)
+        .try_init();
+}
+
+// Emits the per-stage totals as log events, for JSON consumers
+pub fn log_stage_summary() {
+    for s in stage_timings().summary() {
+        info!(
+            stage = s.stage,
+            count = s.count,
+            total_ms = s.total_ms,
+            mean_ms = s.mean_ms,
+            p95_ms = s.p95_ms,
+            max_ms = s.max_ms,
+            "stage timing"
+        );
+    }
+}
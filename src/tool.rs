@@ -1,30 +1,209 @@
+use crate::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
 use dlib_face_recognition::*;
 use image::*;
+use std::os::raw::c_long;
 use std::path::Path;
 
+// dlib's 68-point regions: name, first and last index, and whether the contour closes
+pub const LANDMARK_REGIONS: [(&str, usize, usize, bool); 9] = [
+    ("jaw", 0, 16, false),
+    ("right_brow", 17, 21, false),
+    ("left_brow", 22, 26, false),
+    ("nose_bridge", 27, 30, false),
+    ("nose_base", 30, 35, true),
+    ("right_eye", 36, 41, true),
+    ("left_eye", 42, 47, true),
+    ("outer_lips", 48, 59, true),
+    ("inner_lips", 60, 67, true),
+];
+pub const LANDMARK_COUNT: usize = 68;
+
+// Logs how long `f` took at debug level; prefer a span where the name is static
 pub fn tick<R>(name: &str, f: impl Fn() -> R) -> R {
     let now = std::time::Instant::now();
     let result = f();
-    println!("[{}] elapsed time: {}ms", name, now.elapsed().as_millis());
+    tracing::debug!(
+        stage = name,
+        elapsed_ms = now.elapsed().as_secs_f64() * 1000.0,
+        "elapsed time"
+    );
     result
 }
 pub fn draw_rectangle(image: &mut RgbImage, rect: &Rectangle, colour: Rgb<u8>) {
-    for x in rect.left..rect.right {
-        image.put_pixel(x as u32, rect.top as u32, colour);
-        image.put_pixel(x as u32, rect.bottom as u32, colour);
+    draw_rectangle_thick(image, rect, colour, 1);
+}
+
+// Outline grows inwards from `rect`; anything outside the image is clipped
+pub fn draw_rectangle_thick(
+    image: &mut RgbImage,
+    rect: &Rectangle,
+    colour: Rgb<u8>,
+    thickness: u32,
+) {
+    let thickness = thickness.max(1) as c_long;
+    let (left, top, right, bottom) = (rect.left, rect.top, rect.right, rect.bottom);
+
+    fill_rect(image, left, top, right, top + thickness - 1, colour);
+    fill_rect(image, left, bottom - thickness + 1, right, bottom, colour);
+    fill_rect(image, left, top, left + thickness - 1, bottom, colour);
+    fill_rect(image, right - thickness + 1, top, right, bottom, colour);
+}
+
+pub fn draw_point(image: &mut RgbImage, point: &Point, colour: Rgb<u8>) {
+    fill_rect(
+        image,
+        point.x(),
+        point.y(),
+        point.x() + 1,
+        point.y() + 1,
+        colour,
+    );
+}
+
+// Bresenham line drawn with a square brush `thickness` pixels wide
+pub fn draw_line(image: &mut RgbImage, from: &Point, to: &Point, colour: Rgb<u8>, thickness: u32) {
+    let thickness = thickness.max(1) as c_long;
+    let offset = (thickness - 1) / 2;
+    let (mut x, mut y) = (from.x(), from.y());
+    let dx = (to.x() - x).abs();
+    let dy = -(to.y() - y).abs();
+    let step_x = if x < to.x() { 1 } else { -1 };
+    let step_y = if y < to.y() { 1 } else { -1 };
+    let mut error = dx + dy;
+
+    loop {
+        fill_rect(
+            image,
+            x - offset,
+            y - offset,
+            x - offset + thickness - 1,
+            y - offset + thickness - 1,
+            colour,
+        );
+        if x == to.x() && y == to.y() {
+            break;
+        }
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+    }
+}
+
+/// Connects the 68 dlib landmarks region by region (jaw, brows, nose, eyes,
+/// lips). Any other number of points is drawn as loose dots.
+pub fn draw_landmark_contours(
+    image: &mut RgbImage,
+    landmarks: &[Point],
+    colour: Rgb<u8>,
+    thickness: u32,
+) {
+    if landmarks.len() != LANDMARK_COUNT {
+        for point in landmarks {
+            draw_point(image, point, colour);
+        }
+        return;
     }
 
-    for y in rect.top..rect.bottom {
-        image.put_pixel(rect.left as u32, y as u32, colour);
-        image.put_pixel(rect.right as u32, y as u32, colour);
+    for (_, first, last, closed) in LANDMARK_REGIONS {
+        for i in first..last {
+            draw_line(image, &landmarks[i], &landmarks[i + 1], colour, thickness);
+        }
+        if closed {
+            draw_line(
+                image,
+                &landmarks[last],
+                &landmarks[first],
+                colour,
+                thickness,
+            );
+        }
     }
 }
 
-pub fn draw_point(image: &mut RgbImage, point: &Point, colour: Rgb<u8>) {
-    image.put_pixel(point.x() as u32, point.y() as u32, colour);
-    image.put_pixel(point.x() as u32 + 1, point.y() as u32, colour);
-    image.put_pixel(point.x() as u32 + 1, point.y() as u32 + 1, colour);
-    image.put_pixel(point.x() as u32, point.y() as u32 + 1, colour);
+// Labels a box just above its top edge, or inside it when the box touches the top
+pub fn draw_label(image: &mut RgbImage, rect: &Rectangle, text: &str, colour: Rgb<u8>, scale: u32) {
+    let (_, label_height) = text_size(text, scale);
+    let above = rect.top - label_height as c_long - 2;
+    let label_y = if above >= 0 { above } else { rect.top + 2 };
+    draw_text(image, rect.left, label_y, text, colour, scale);
+}
+
+// Line thickness and text scale that stay visible on large photos
+pub fn stroke_scale(image: &RgbImage) -> u32 {
+    (image.width().max(image.height()) / 400).max(1)
+}
+
+// Draws `text` with its top-left corner at (x, y); pixels outside the image are skipped
+pub fn draw_text(
+    image: &mut RgbImage,
+    x: c_long,
+    y: c_long,
+    text: &str,
+    colour: Rgb<u8>,
+    scale: u32,
+) {
+    let scale = scale.max(1) as c_long;
+    let advance = (GLYPH_WIDTH as c_long + 1) * scale;
+
+    for (i, c) in text.chars().enumerate() {
+        let origin_x = x + i as c_long * advance;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH as c_long {
+                if bits & (1 << (GLYPH_WIDTH as c_long - 1 - col)) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        put_pixel_checked(
+                            image,
+                            origin_x + col * scale + dx,
+                            y + row as c_long * scale + dy,
+                            colour,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let scale = scale.max(1);
+    let len = text.chars().count() as u32;
+    (len * (GLYPH_WIDTH + 1) * scale, GLYPH_HEIGHT * scale)
+}
+
+// Fills the inclusive rectangle (x0, y0)-(x1, y1), clipped to the image
+fn fill_rect(
+    image: &mut RgbImage,
+    x0: c_long,
+    y0: c_long,
+    x1: c_long,
+    y1: c_long,
+    colour: Rgb<u8>,
+) {
+    let x0 = x0.max(0);
+    let y0 = y0.max(0);
+    let x1 = x1.min(image.width() as c_long - 1);
+    let y1 = y1.min(image.height() as c_long - 1);
+
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            image.put_pixel(x as u32, y as u32, colour);
+        }
+    }
+}
+
+fn put_pixel_checked(image: &mut RgbImage, x: c_long, y: c_long, colour: Rgb<u8>) {
+    if x >= 0 && y >= 0 && x < image.width() as c_long && y < image.height() as c_long {
+        image.put_pixel(x as u32, y as u32, colour);
+    }
 }
 
 pub fn get_full_file_name(file_path: &str) -> String {
@@ -34,3 +213,20 @@ pub fn get_full_file_name(file_path: &str) -> String {
         .map(|name| name.to_string())
         .unwrap_or_else(|| "".to_string())
 }
+
+// Quotes a value containing a comma, quote or line break, doubling inner quotes
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Escapes text for use in XML or HTML content and attribute values
+pub(crate) fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
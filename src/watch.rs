@@ -0,0 +1,61 @@
+use crate::feature::Features;
+use crate::photos::{is_image_file, photo_child_id};
+use notify_debouncer_mini::new_debouncer;
+use notify_debouncer_mini::notify::RecursiveMode;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+use tracing::{error, info, info_span, warn};
+
+/// Watches `photos_dir` and enrolls every new or changed .jpg/.png through
+/// `Features::enroll_photo`, which updates that child's aggregates so
+/// identification picks up the new photo. A changed photo replaces the atomics
+/// previously extracted from the same path in the same transaction. Events
+/// for the same file within `debounce` are merged, so half-copied files are
+/// picked up once they settle. Blocks until the watcher fails.
+pub fn watch_and_enroll(
+    features: &mut Features,
+    photos_dir: &str,
+    debounce: Duration,
+) -> Result<(), String> {
+    let (sender, receiver) = mpsc::channel();
+    let mut debouncer = new_debouncer(debounce, sender).map_err(|e| e.to_string())?;
+    debouncer
+        .watcher()
+        .watch(Path::new(photos_dir), RecursiveMode::Recursive)
+        .map_err(|e| e.to_string())?;
+    info!(dir = photos_dir, "Watching for new photos");
+
+    for result in receiver {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                warn!(error = %e, "Watch error");
+                continue;
+            }
+        };
+        for event in events {
+            // Deleted and moved-away files also raise events
+            if event.path.is_file() && is_image_file(&event.path) {
+                enroll_changed_photo(features, &event.path);
+            }
+        }
+    }
+    Err(String::from("Watcher stopped"))
+}
+
+fn enroll_changed_photo(features: &mut Features, photo_path: &Path) {
+    let Some(photo_path) = photo_path.to_str() else {
+        return;
+    };
+    let Some(child_id) = photo_child_id(Path::new(photo_path)) else {
+        warn!(photo = photo_path, "No child ID in file name");
+        return;
+    };
+    let _span = info_span!("watch_enroll", child_id = %child_id).entered();
+
+    // Keeps the earlier encodings when the photo can't be enrolled, e.g. it is still being written
+    if let Err(e) = features.enroll_photo(&child_id, photo_path) {
+        error!(photo = photo_path, error = %e, "Error enrolling photo");
+    }
+}